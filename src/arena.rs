@@ -0,0 +1,473 @@
+use tree::Hierarchical;
+use tree::Node;
+use tree::Traversable;
+
+
+/// Index of a node in an `Arena`, it stays valid as long as the arena exists,
+/// also after the node has been detached.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+
+impl NodeId {
+	pub fn index(self) -> usize {
+		self.0
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry<T> {
+	data: T,
+	parent: Option<NodeId>,
+	first_child: Option<NodeId>,
+	last_child: Option<NodeId>,
+	previous_sibling: Option<NodeId>,
+	next_sibling: Option<NodeId>,
+}
+
+
+/// Tree stored in a single `Vec`, every node links to its parent and siblings
+/// so that each step along an axis takes constant time.
+///
+/// Methods taking a `NodeId` of another arena panic when it is out of range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arena<T> {
+	entries: Vec<Entry<T>>,
+}
+
+
+impl<T> Arena<T> {
+	pub fn new(root: T) -> Arena<T> {
+		Arena {
+			entries: vec![Entry::new(root)],
+		}
+	}
+
+	pub fn root(&self) -> NodeId {
+		NodeId(0)
+	}
+
+	/// Nodes in the arena, including detached ones.
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn get(&self, id: NodeId) -> &T {
+		&self.entries[id.0].data
+	}
+
+	pub fn get_mut(&mut self, id: NodeId) -> &mut T {
+		&mut self.entries[id.0].data
+	}
+
+	pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+		self.entries[id.0].parent
+	}
+
+	pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+		self.entries[id.0].first_child
+	}
+
+	pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+		self.entries[id.0].last_child
+	}
+
+	pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+		self.entries[id.0].previous_sibling
+	}
+
+	pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+		self.entries[id.0].next_sibling
+	}
+
+	/// Adds a node as the last child of `parent`.
+	pub fn append(&mut self, parent: NodeId, data: T) -> NodeId {
+		let last = self.entries[parent.0].last_child;
+		let id = self.push(data);
+		self.attach(id, Some(parent), last, None);
+		id
+	}
+
+	/// Adds a node as the first child of `parent`.
+	pub fn prepend(&mut self, parent: NodeId, data: T) -> NodeId {
+		let first = self.entries[parent.0].first_child;
+		let id = self.push(data);
+		self.attach(id, Some(parent), None, first);
+		id
+	}
+
+	/// Adds a node right before `sibling`, which must have a parent.
+	pub fn insert_before(&mut self, sibling: NodeId, data: T) -> NodeId {
+		let (parent, previous, next) = {
+			let entry = &self.entries[sibling.0];
+			assert!(entry.parent.is_some(), "a node without parent has no siblings");
+			(entry.parent, entry.previous_sibling, Some(sibling))
+		};
+		let id = self.push(data);
+		self.attach(id, parent, previous, next);
+		id
+	}
+
+	/// Adds a node right after `sibling`, which must have a parent.
+	pub fn insert_after(&mut self, sibling: NodeId, data: T) -> NodeId {
+		let (parent, previous, next) = {
+			let entry = &self.entries[sibling.0];
+			assert!(entry.parent.is_some(), "a node without parent has no siblings");
+			(entry.parent, Some(sibling), entry.next_sibling)
+		};
+		let id = self.push(data);
+		self.attach(id, parent, previous, next);
+		id
+	}
+
+	/// Unlinks the node with its subtree from its parent and siblings. The
+	/// subtree keeps its ids and can be attached again with `reattach`.
+	pub fn detach(&mut self, id: NodeId) {
+		let (parent, previous, next) = {
+			let entry = &mut self.entries[id.0];
+			(entry.parent.take(), entry.previous_sibling.take(), entry.next_sibling.take())
+		};
+		match previous {
+			Some(previous) => self.entries[previous.0].next_sibling = next,
+			None => if let Some(parent) = parent {
+				self.entries[parent.0].first_child = next;
+			},
+		}
+		match next {
+			Some(next) => self.entries[next.0].previous_sibling = previous,
+			None => if let Some(parent) = parent {
+				self.entries[parent.0].last_child = previous;
+			},
+		}
+	}
+
+	/// Attaches a detached node as the last child of `parent`, which must not
+	/// lie in the subtree of the node.
+	pub fn reattach(&mut self, id: NodeId, parent: NodeId) {
+		assert!(self.entries[id.0].parent.is_none() && id != self.root(), "only detached nodes can be attached");
+		assert!(!self.ancestors(parent).any(|ancestor| ancestor == id) && parent != id, "a node can not be attached below itself");
+		let last = self.entries[parent.0].last_child;
+		self.attach(id, Some(parent), last, None);
+	}
+
+	/// Ancestors of the node up to the root, nearest first.
+	pub fn ancestors(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.parent(id), AxisKind::Ancestors)
+	}
+
+	pub fn children(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.first_child(id), AxisKind::FollowingSiblings)
+	}
+
+	/// Siblings after the node, nearest first.
+	pub fn following_siblings(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.next_sibling(id), AxisKind::FollowingSiblings)
+	}
+
+	/// Siblings before the node, nearest first.
+	pub fn preceding_siblings(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.previous_sibling(id), AxisKind::PrecedingSiblings)
+	}
+
+	/// Descendants of the node in document order.
+	pub fn descendants(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.first_child(id), AxisKind::Descendants(id))
+	}
+
+	/// Nodes after the node in document order, without its descendants.
+	pub fn following(&self, id: NodeId) -> Axis<'_, T> {
+		Axis::new(self, self.next_outside(id, None), AxisKind::Following)
+	}
+
+	/// Nodes before the node in reverse document order, without its ancestors.
+	pub fn preceding(&self, id: NodeId) -> Axis<'_, T> {
+		let mut axis = Axis::new(self, Some(id), AxisKind::Preceding(self.parent(id)));
+		axis.next();
+		axis
+	}
+
+	/// Copies the subtree of the node into an owning tree.
+	pub fn to_node(&self, id: NodeId) -> Node<T> where T: Clone {
+		// nodes whose children are being copied, each with its next child
+		let mut stack = vec![(Node::new(self.get(id).clone()), self.first_child(id))];
+		loop {
+			let next = stack.last_mut().and_then(|&mut (_, ref mut next)| next.take());
+			match next {
+				Some(child) => {
+					stack.last_mut().unwrap().1 = self.next_sibling(child);
+					stack.push((Node::new(self.get(child).clone()), self.first_child(child)));
+				},
+				None => {
+					let (node, _) = stack.pop().unwrap();
+					match stack.last_mut() {
+						Some(&mut (ref mut parent, _)) => {
+							parent.add_node(node);
+						},
+						None => return node,
+					}
+				},
+			}
+		}
+	}
+
+	fn push(&mut self, data: T) -> NodeId {
+		self.entries.push(Entry::new(data));
+		NodeId(self.entries.len() - 1)
+	}
+
+	fn attach(&mut self, id: NodeId, parent: Option<NodeId>, previous: Option<NodeId>, next: Option<NodeId>) {
+		{
+			let entry = &mut self.entries[id.0];
+			entry.parent = parent;
+			entry.previous_sibling = previous;
+			entry.next_sibling = next;
+		}
+		match previous {
+			Some(previous) => self.entries[previous.0].next_sibling = Some(id),
+			None => if let Some(parent) = parent {
+				self.entries[parent.0].first_child = Some(id);
+			},
+		}
+		match next {
+			Some(next) => self.entries[next.0].previous_sibling = Some(id),
+			None => if let Some(parent) = parent {
+				self.entries[parent.0].last_child = Some(id);
+			},
+		}
+	}
+
+	// next node in document order that is not a descendant of `id`, without leaving `scope`
+	fn next_outside(&self, id: NodeId, scope: Option<NodeId>) -> Option<NodeId> {
+		let mut current = id;
+		loop {
+			if Some(current) == scope {
+				return None;
+			}
+			if let Some(next) = self.next_sibling(current) {
+				return Some(next);
+			}
+			current = self.parent(current)?;
+		}
+	}
+
+	fn last_descendant(&self, id: NodeId) -> NodeId {
+		let mut current = id;
+		while let Some(last) = self.last_child(current) {
+			current = last;
+		}
+		current
+	}
+}
+
+
+impl<T> Entry<T> {
+	fn new(data: T) -> Entry<T> {
+		Entry {
+			data,
+			parent: None,
+			first_child: None,
+			last_child: None,
+			previous_sibling: None,
+			next_sibling: None,
+		}
+	}
+}
+
+
+impl<T> From<Node<T>> for Arena<T> {
+	fn from(node: Node<T>) -> Arena<T> {
+		let (data, children) = node.split();
+		let mut arena = Arena::new(data);
+		let mut stack = vec![(arena.root(), children)];
+		while let Some(&mut (parent, ref mut children)) = stack.last_mut() {
+			match children.next() {
+				Some(child) => {
+					let (data, children) = child.split();
+					let id = arena.append(parent, data);
+					stack.push((id, children));
+				},
+				None => {
+					stack.pop();
+				},
+			}
+		}
+		arena
+	}
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AxisKind {
+	Ancestors,
+	FollowingSiblings,
+	PrecedingSiblings,
+	Descendants(NodeId), // node whose descendants are visited
+	Following,
+	Preceding(Option<NodeId>), // nearest ancestor not yet skipped
+}
+
+
+/// Iterator over the ids of the nodes along an axis of a node.
+#[derive(Debug, Clone)]
+pub struct Axis<'a, T: 'a> {
+	arena: &'a Arena<T>,
+	next: Option<NodeId>,
+	kind: AxisKind,
+}
+
+
+impl<'a, T> Axis<'a, T> {
+	fn new(arena: &'a Arena<T>, next: Option<NodeId>, kind: AxisKind) -> Axis<'a, T> {
+		Axis {
+			arena,
+			next,
+			kind,
+		}
+	}
+}
+
+
+impl<'a, T> Iterator for Axis<'a, T> {
+	type Item = NodeId;
+	fn next(&mut self) -> Option<NodeId> {
+		let current = self.next?;
+		let arena = self.arena;
+		self.next = match self.kind {
+			AxisKind::Ancestors => arena.parent(current),
+			AxisKind::FollowingSiblings => arena.next_sibling(current),
+			AxisKind::PrecedingSiblings => arena.previous_sibling(current),
+			AxisKind::Descendants(scope) => arena.first_child(current).or_else(|| arena.next_outside(current, Some(scope))),
+			AxisKind::Following => arena.first_child(current).or_else(|| arena.next_outside(current, None)),
+			AxisKind::Preceding(mut ancestor) => {
+				let mut node = current;
+				let next = loop {
+					if let Some(previous) = arena.previous_sibling(node) {
+						break Some(arena.last_descendant(previous));
+					}
+					match arena.parent(node) {
+						Some(parent) if Some(parent) == ancestor => {
+							ancestor = arena.parent(parent);
+							node = parent;
+						},
+						parent => break parent,
+					}
+				};
+				self.kind = AxisKind::Preceding(ancestor);
+				next
+			},
+		};
+		Some(current)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use tree::Hierarchical;
+	use std::panic;
+	use tree::Node;
+	use super::*;
+
+	//        0
+	//      /   \
+	//     1     4
+	//    / \     \
+	//   2   3     5
+	fn sample() -> Arena<usize> {
+		let mut node = Node::new(0);
+		{
+			let first = node.add_child(1);
+			first.add_child(2);
+			first.add_child(3);
+		}
+		node.add_child(4).add_child(5);
+		Arena::from(node)
+	}
+
+	fn data(arena: &Arena<usize>, ids: Axis<usize>) -> Vec<usize> {
+		ids.map(|id| *arena.get(id)).collect()
+	}
+
+	fn find(arena: &Arena<usize>, value: usize) -> NodeId {
+		arena.descendants(arena.root()).find(|id| *arena.get(*id) == value).unwrap()
+	}
+
+	#[test]
+	fn navigate_axes() {
+		let arena = sample();
+		let root = arena.root();
+		let three = find(&arena, 3);
+		assert_eq!(data(&arena, arena.descendants(root)), vec![1, 2, 3, 4, 5]);
+		assert_eq!(data(&arena, arena.children(root)), vec![1, 4]);
+		assert_eq!(data(&arena, arena.ancestors(three)), vec![1, 0]);
+		assert_eq!(data(&arena, arena.preceding_siblings(three)), vec![2]);
+		assert_eq!(data(&arena, arena.following_siblings(find(&arena, 1))), vec![4]);
+		assert_eq!(data(&arena, arena.following(find(&arena, 2))), vec![3, 4, 5]);
+		assert_eq!(data(&arena, arena.following(find(&arena, 1))), vec![4, 5]);
+		assert_eq!(data(&arena, arena.preceding(find(&arena, 5))), vec![3, 2, 1]);
+		assert_eq!(data(&arena, arena.preceding(three)), vec![2]);
+		assert_eq!(data(&arena, arena.descendants(find(&arena, 1))), vec![2, 3]);
+		assert_eq!(arena.parent(root), None);
+		assert_eq!(arena.last_child(root).map(|id| *arena.get(id)), Some(4));
+	}
+
+	#[test]
+	fn insert_and_move_nodes() {
+		let mut arena = sample();
+		let root = arena.root();
+		let one = find(&arena, 1);
+		let four = find(&arena, 4);
+		arena.insert_before(four, 6);
+		arena.insert_after(four, 7);
+		arena.prepend(root, 8);
+		assert_eq!(data(&arena, arena.children(root)), vec![8, 1, 6, 4, 7]);
+		arena.detach(one);
+		assert_eq!(data(&arena, arena.children(root)), vec![8, 6, 4, 7]);
+		assert_eq!(arena.parent(one), None);
+		assert_eq!(data(&arena, arena.descendants(one)), vec![2, 3]);
+		arena.reattach(one, four);
+		assert_eq!(data(&arena, arena.children(four)), vec![5, 1]);
+		assert_eq!(data(&arena, arena.ancestors(find(&arena, 3))), vec![1, 4, 0]);
+		*arena.get_mut(one) = 9;
+		assert_eq!(arena.to_node(four).get_child_data(1), &9);
+		assert_eq!(arena.len(), 9);
+	}
+
+	#[test]
+	fn insert_next_to_root() {
+		let mut arena = sample();
+		let root = arena.root();
+		for before in &[true, false] {
+			let result = panic::catch_unwind(panic::AssertUnwindSafe(|| if *before { arena.insert_before(root, 6) } else { arena.insert_after(root, 6) }));
+			assert!(result.is_err());
+		}
+		assert_eq!(arena.len(), 6);
+	}
+
+	#[test]
+	fn copy_deep_tree() {
+		let mut arena = Arena::new(0);
+		let mut parent = arena.root();
+		for depth in 1..100_000 {
+			parent = arena.append(parent, depth);
+		}
+		let node = arena.to_node(arena.root());
+		assert_eq!(node.iter().count(), 100_000);
+		assert_eq!(node.iter().last().map(|node| *node.get_data()), Some(99_999));
+	}
+
+	#[test]
+	#[should_panic]
+	fn reattach_below_itself() {
+		let mut arena = sample();
+		let one = find(&arena, 1);
+		let three = find(&arena, 3);
+		arena.detach(one);
+		arena.reattach(one, three);
+	}
+}
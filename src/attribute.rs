@@ -1,4 +1,7 @@
 use std::fmt;
+use entity;
+use namespace::XmlQName;
+use token::QuoteKind;
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct XmlAttribute {
@@ -8,14 +11,19 @@ pub struct XmlAttribute {
 
     /// Attribute value.
     // How to make the value an allowed value?
-    pub value: String
+    pub value: String,
+
+    /// Quote the value is delimited with.
+    pub quote: QuoteKind,
+
+    /// Namespace the prefix of the name is bound to, set by the parser.
+    pub namespace: Option<String>,
 }
 
 
-impl<'a> fmt::Display for XmlAttribute {
+impl fmt::Display for XmlAttribute {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // Need to filter out the resistriced values from the value
-        write!(f, "{}=\"{}\"", self.name, self.value)
+        write!(f, "{}={}{}{}", self.name, self.quote, entity::escape_attribute(&self.value, self.quote), self.quote)
     }
 }
 
@@ -24,13 +32,25 @@ impl XmlAttribute {
     /// Creates a borrowed attribute using the provided borrowed name and a borrowed string value.
     #[inline]
     pub fn new(name: String, value: String) -> Option<XmlAttribute> {
-        Some(XmlAttribute { name: name, value: value, })
+        XmlAttribute::new_quoted(name, value, QuoteKind::Double)
+    }
+
+    /// Creates an attribute whose value is delimited with the given quote.
+    #[inline]
+    pub fn new_quoted(name: String, value: String, quote: QuoteKind) -> Option<XmlAttribute> {
+        Some(XmlAttribute { name, value, quote, namespace: None, })
+    }
+
+    /// Name split at its prefix, with its namespace.
+    pub fn qname(&self) -> XmlQName {
+        XmlQName::new(&self.name, self.namespace.clone())
     }
 }
 
 
 #[cfg(test)]
 mod tests {
+	use token::QuoteKind;
 	use super::{XmlAttribute};
 
 	#[test]
@@ -45,4 +65,17 @@ mod tests {
 		let attribute = XmlAttribute::new("name".to_string(), "value".to_string()).unwrap();
 		assert_eq!("name=\"value\"".to_owned(), format!("{}", attribute));
 	}
+
+	#[test]
+	fn display_single_quoted_attribute() {
+		let attribute = XmlAttribute::new_quoted("name".to_string(), "\"value\"".to_string(), QuoteKind::Single).unwrap();
+		assert_eq!(attribute.quote, QuoteKind::Single);
+		assert_eq!("name='\"value\"'".to_owned(), format!("{}", attribute));
+	}
+
+	#[test]
+	fn display_escaped_attribute() {
+		let attribute = XmlAttribute::new("name".to_string(), "<\"a\" & 'b'>".to_string()).unwrap();
+		assert_eq!("name=\"&lt;&quot;a&quot; &amp; 'b'&gt;\"".to_owned(), format!("{}", attribute));
+	}
 }
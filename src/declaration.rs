@@ -0,0 +1,184 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use errors::ExtractError;
+use errors::ExtractResult;
+use span::XmlPosition;
+use tokenizer::XmlValidation;
+
+
+/// The `<?xml version="1.0" encoding="UTF-8" standalone="yes"?>` declaration
+/// at the start of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XmlDeclaration {
+	pub version: String,
+	pub encoding: Option<String>,
+	pub standalone: Option<bool>,
+}
+
+
+impl XmlDeclaration {
+	pub fn new(version: String, encoding: Option<String>, standalone: Option<bool>) -> XmlDeclaration {
+		XmlDeclaration { version, encoding, standalone }
+	}
+
+	/// Parses the pseudo attributes following `<?xml`, `position` is the start
+	/// of the declaration and is used for errors.
+	pub fn parse(data: &str, position: XmlPosition) -> ExtractResult<XmlDeclaration> {
+		let invalid = |text: &str| ExtractError::InvalidDeclaration(text.to_string(), position);
+		let mut attributes = parse_pseudo_attributes(data, position)?.into_iter().peekable();
+		let version = match attributes.next() {
+			Some((ref name, ref value)) if name == "version" => {
+				if !is_valid_version(value) {
+					return Err(invalid(value));
+				}
+				value.clone()
+			},
+			Some((name, _)) => return Err(invalid(&name)),
+			None => return Err(invalid("version")),
+		};
+		let mut encoding = None;
+		if attributes.peek().is_some_and(|attribute| attribute.0 == "encoding") {
+			let (_, value) = attributes.next().unwrap();
+			if !is_valid_encoding(&value) {
+				return Err(invalid(&value));
+			}
+			encoding = Some(value);
+		}
+		let mut standalone = None;
+		if attributes.peek().is_some_and(|attribute| attribute.0 == "standalone") {
+			let (_, value) = attributes.next().unwrap();
+			standalone = match value.as_str() {
+				"yes" => Some(true),
+				"no" => Some(false),
+				_ => return Err(invalid(&value)),
+			};
+		}
+		match attributes.next() {
+			Some((name, _)) => Err(invalid(&name)),
+			None => Ok(XmlDeclaration::new(version, encoding, standalone)),
+		}
+	}
+}
+
+
+impl fmt::Display for XmlDeclaration {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "version=\"{}\"", self.version)?;
+		if let Some(ref encoding) = self.encoding {
+			write!(f, " encoding=\"{}\"", encoding)?;
+		}
+		if let Some(standalone) = self.standalone {
+			write!(f, " standalone=\"{}\"", if standalone { "yes" } else { "no" })?;
+		}
+		Ok(())
+	}
+}
+
+
+/// VersionNum ::= '1.' [0-9]+
+fn is_valid_version(value: &str) -> bool {
+	value.starts_with("1.") && value.len() > 2 && value[2..].chars().all(|c| c.is_ascii_digit())
+}
+
+
+/// EncName ::= [A-Za-z] ([A-Za-z0-9._] | '-')*
+fn is_valid_encoding(value: &str) -> bool {
+	let mut chars = value.chars();
+	chars.next().is_some_and(|c| c.is_ascii_alphabetic()) &&
+		chars.all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> bool {
+	let mut skipped = false;
+	while chars.peek().is_some_and(|c| c.is_whitespace_in_xml()) {
+		chars.next();
+		skipped = true;
+	}
+	skipped
+}
+
+
+/// Splits `name="value" name='value'` into name and value pairs.
+fn parse_pseudo_attributes(data: &str, position: XmlPosition) -> ExtractResult<Vec<(String, String)>> {
+	let mut attributes = vec![];
+	let mut chars = data.chars().peekable();
+	loop {
+		let separated = skip_whitespace(&mut chars);
+		if chars.peek().is_none() {
+			return Ok(attributes);
+		}
+		let mut name = String::new();
+		while let Some(&c) = chars.peek() {
+			if !c.is_ascii_alphanumeric() {
+				break;
+			}
+			name.push(c);
+			chars.next();
+		}
+		if name.is_empty() || (!separated && !attributes.is_empty()) {
+			return Err(ExtractError::InvalidDeclaration(data.to_string(), position));
+		}
+		skip_whitespace(&mut chars);
+		if chars.next() != Some('=') {
+			return Err(ExtractError::InvalidDeclaration(name, position));
+		}
+		skip_whitespace(&mut chars);
+		let quote = match chars.next() {
+			Some(c) if c == '"' || c == '\'' => c,
+			_ => return Err(ExtractError::InvalidDeclaration(name, position)),
+		};
+		let mut value = String::new();
+		loop {
+			match chars.next() {
+				Some(c) if c == quote => break,
+				Some(c) => value.push(c),
+				None => return Err(ExtractError::InvalidDeclaration(name, position)),
+			}
+		}
+		attributes.push((name, value));
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use errors::ExtractError;
+	use span::XmlPosition;
+	use super::XmlDeclaration;
+
+	#[test]
+	fn parse_declaration() {
+		let declaration = XmlDeclaration::parse("version=\"1.0\" encoding='UTF-8' standalone=\"yes\"", XmlPosition::start()).unwrap();
+		assert_eq!(declaration, XmlDeclaration::new("1.0".to_string(), Some("UTF-8".to_string()), Some(true)));
+	}
+	#[test]
+	fn parse_version_only() {
+		let declaration = XmlDeclaration::parse("version = '1.1' ", XmlPosition::start()).unwrap();
+		assert_eq!(declaration, XmlDeclaration::new("1.1".to_string(), None, None));
+	}
+	#[test]
+	fn parse_invalid_declaration() {
+		let invalid = |data: &str| match XmlDeclaration::parse(data, XmlPosition::start()) {
+			Err(ExtractError::InvalidDeclaration(text, _)) => text,
+			other => panic!("unexpected result {:?}", other),
+		};
+		assert_eq!(invalid(""), "version");
+		assert_eq!(invalid("encoding=\"UTF-8\""), "encoding");
+		assert_eq!(invalid("version=\"2.0\""), "2.0");
+		assert_eq!(invalid("version=\"1.0\" standalone=\"maybe\""), "maybe");
+		assert_eq!(invalid("version=\"1.0\" standalone=\"no\" encoding=\"UTF-8\""), "encoding");
+		assert_eq!(invalid("version=\"1.0\" encoding=\"8bit\""), "8bit");
+		assert_eq!(invalid("version=\"1.0"), "version");
+		assert_eq!(invalid("version=\"1.0\"encoding=\"UTF-8\""), "version=\"1.0\"encoding=\"UTF-8\"");
+		assert!(matches!(XmlDeclaration::parse("version=\"1.0\"\u{A0}encoding=\"UTF-8\"", XmlPosition::start()), Err(ExtractError::InvalidDeclaration(_, _))));
+		assert!(matches!(XmlDeclaration::parse("\u{2003}version=\"1.0\"", XmlPosition::start()), Err(ExtractError::InvalidDeclaration(_, _))));
+		assert!(XmlDeclaration::parse("\tversion=\"1.0\"\r\n", XmlPosition::start()).is_ok());
+	}
+	#[test]
+	fn display_declaration() {
+		let declaration = XmlDeclaration::new("1.0".to_string(), Some("UTF-8".to_string()), Some(false));
+		assert_eq!(declaration.to_string(), "version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"".to_string());
+	}
+}
@@ -0,0 +1,884 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use entity;
+use errors::ExtractError;
+use errors::ExtractResult;
+use span::XmlPosition;
+use tokenizer::XmlValidation;
+
+
+/// The `<!DOCTYPE root SYSTEM "root.dtd" [ ... ]>` declaration of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlDoctype {
+	/// Name of the root element.
+	pub name: String,
+	pub external_id: Option<XmlExternalId>,
+	/// Declarations of the internal subset, in document order.
+	pub declarations: Vec<XmlMarkupDeclaration>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XmlExternalId {
+	System(String), // SYSTEM "system"
+	Public(String, Option<String>), // PUBLIC "public" "system", the system literal is optional for notations only
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlMarkupDeclaration {
+	Element(XmlElementDeclaration), // <!ELEMENT name content>
+	AttributeList(XmlAttributeListDeclaration), // <!ATTLIST element attributes>
+	Entity(XmlEntityDeclaration), // <!ENTITY name "value">
+	Notation(XmlNotationDeclaration), // <!NOTATION name SYSTEM "system">
+	ParameterEntityReference(String), // %name;
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlElementDeclaration {
+	pub name: String,
+	pub content: XmlContentSpec,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlContentSpec {
+	Empty, // EMPTY
+	Any, // ANY
+	Mixed(Vec<String>), // (#PCDATA|name)*
+	Children(XmlContentParticle), // (name, (a|b)*)
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlContentParticle {
+	pub kind: XmlParticleKind,
+	pub repetition: XmlRepetition,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlParticleKind {
+	Name(String), // name
+	Sequence(Vec<XmlContentParticle>), // (a, b)
+	Choice(Vec<XmlContentParticle>), // (a | b)
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XmlRepetition {
+	One, // exactly once
+	Optional, // ?
+	ZeroOrMore, // *
+	OneOrMore, // +
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlAttributeListDeclaration {
+	pub element: String,
+	pub attributes: Vec<XmlAttributeDefinition>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlAttributeDefinition {
+	pub name: String,
+	pub kind: XmlAttributeType,
+	pub default: XmlAttributeDefault,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XmlAttributeType {
+	CData,
+	Id,
+	IdRef,
+	IdRefs,
+	Entity,
+	Entities,
+	NmToken,
+	NmTokens,
+	Notation(Vec<String>), // NOTATION (a|b)
+	Enumeration(Vec<String>), // (a|b)
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XmlAttributeDefault {
+	Required, // #REQUIRED
+	Implied, // #IMPLIED
+	Fixed(String), // #FIXED "value"
+	Value(String), // "value"
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlEntityDeclaration {
+	pub name: String,
+	/// Parameter entities, `<!ENTITY % name ...>`, are only referenced in the DTD.
+	pub parameter: bool,
+	pub value: XmlEntityValue,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlEntityValue {
+	/// Replacement text as written, references in it are not expanded.
+	Internal(String),
+	/// External entity with the notation of unparsed entities.
+	External(XmlExternalId, Option<String>),
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlNotationDeclaration {
+	pub name: String,
+	pub external_id: XmlExternalId,
+}
+
+
+impl XmlDoctype {
+	pub fn new(name: String, external_id: Option<XmlExternalId>, declarations: Vec<XmlMarkupDeclaration>) -> XmlDoctype {
+		XmlDoctype { name, external_id, declarations }
+	}
+
+	/// Parses what follows `<!DOCTYPE` up to the closing `>`, `position` is the
+	/// position of the text and is used for errors.
+	pub fn parse(text: &str, position: XmlPosition) -> ExtractResult<XmlDoctype> {
+		let mut reader = XmlDtdReader::new(text, position);
+		reader.expect_whitespace()?;
+		let name = reader.read_name()?;
+		let separated = reader.skip_whitespace();
+		let external_id = match reader.peek() {
+			Some('S') | Some('P') if separated => Some(reader.read_external_id(false)?),
+			_ => None,
+		};
+		reader.skip_whitespace();
+		let mut declarations = vec![];
+		if reader.peek() == Some('[') {
+			reader.next();
+			declarations = reader.read_internal_subset()?;
+			reader.skip_whitespace();
+		}
+		reader.expect_end()?;
+		Ok(XmlDoctype::new(name, external_id, declarations))
+	}
+
+	pub fn elements(&self) -> impl Iterator<Item = &XmlElementDeclaration> {
+		self.declarations.iter().filter_map(|declaration| match declaration {
+			XmlMarkupDeclaration::Element(element) => Some(element),
+			_ => None,
+		})
+	}
+	pub fn attribute_lists(&self) -> impl Iterator<Item = &XmlAttributeListDeclaration> {
+		self.declarations.iter().filter_map(|declaration| match declaration {
+			XmlMarkupDeclaration::AttributeList(list) => Some(list),
+			_ => None,
+		})
+	}
+	pub fn entities(&self) -> impl Iterator<Item = &XmlEntityDeclaration> {
+		self.declarations.iter().filter_map(|declaration| match declaration {
+			XmlMarkupDeclaration::Entity(entity) => Some(entity),
+			_ => None,
+		})
+	}
+	pub fn notations(&self) -> impl Iterator<Item = &XmlNotationDeclaration> {
+		self.declarations.iter().filter_map(|declaration| match declaration {
+			XmlMarkupDeclaration::Notation(notation) => Some(notation),
+			_ => None,
+		})
+	}
+	pub fn element(&self, name: &str) -> Option<&XmlElementDeclaration> {
+		self.elements().find(|element| element.name == name)
+	}
+	/// The general entity with the given name, the first declaration is binding.
+	pub fn entity(&self, name: &str) -> Option<&XmlEntityDeclaration> {
+		self.entities().find(|entity| !entity.parameter && entity.name == name)
+	}
+}
+
+
+impl XmlContentParticle {
+	pub fn new(kind: XmlParticleKind, repetition: XmlRepetition) -> XmlContentParticle {
+		XmlContentParticle { kind, repetition }
+	}
+}
+
+
+/// Groups nested deeper in a content model are rejected, parsing them and
+/// matching elements against them would recurse once per level.
+const MAX_GROUP_DEPTH: usize = 64;
+
+
+/// Reads the parts of a DTD, keeping track of the position for errors.
+struct XmlDtdReader<'t> {
+	chars: Peekable<Chars<'t>>,
+	position: XmlPosition,
+	depth: usize, // groups open in the content model being read
+}
+
+
+impl<'t> XmlDtdReader<'t> {
+	fn new(text: &'t str, position: XmlPosition) -> XmlDtdReader<'t> {
+		XmlDtdReader { chars: text.chars().peekable(), position, depth: 0 }
+	}
+	fn peek(&mut self) -> Option<char> {
+		self.chars.peek().cloned()
+	}
+	fn next(&mut self) -> Option<char> {
+		let c = self.chars.next();
+		if let Some(c) = c {
+			self.position.advance(c);
+		}
+		c
+	}
+	/// Error for the text at the current position.
+	fn invalid(&mut self) -> ExtractError {
+		let position = self.position;
+		let mut text = String::new();
+		while let Some(c) = self.peek() {
+			if c.is_whitespace_in_xml() || (!text.is_empty() && !c.is_valid_char_in_element_name()) {
+				break;
+			}
+			text.push(c);
+			self.next();
+		}
+		ExtractError::InvalidDoctype(text, position)
+	}
+	fn skip_whitespace(&mut self) -> bool {
+		let mut skipped = false;
+		while self.peek().is_some_and(|c| c.is_whitespace_in_xml()) {
+			self.next();
+			skipped = true;
+		}
+		skipped
+	}
+	fn expect_whitespace(&mut self) -> ExtractResult<()> {
+		if self.skip_whitespace() {
+			Ok(())
+		}
+		else {
+			Err(self.invalid())
+		}
+	}
+	fn expect(&mut self, expected: &str) -> ExtractResult<()> {
+		for e in expected.chars() {
+			if self.peek() != Some(e) {
+				return Err(self.invalid());
+			}
+			self.next();
+		}
+		Ok(())
+	}
+	fn expect_end(&mut self) -> ExtractResult<()> {
+		match self.peek() {
+			None => Ok(()),
+			Some(_) => Err(self.invalid()),
+		}
+	}
+	fn read_name(&mut self) -> ExtractResult<String> {
+		match self.peek() {
+			Some(c) if c.is_valid_first_char_in_element_name() => (),
+			_ => return Err(self.invalid()),
+		}
+		self.read_name_token()
+	}
+	/// Reads a Nmtoken, a name that may start with any name character.
+	fn read_name_token(&mut self) -> ExtractResult<String> {
+		let mut name = String::new();
+		while let Some(c) = self.peek() {
+			if !c.is_valid_char_in_element_name() {
+				break;
+			}
+			name.push(c);
+			self.next();
+		}
+		if name.is_empty() {
+			return Err(self.invalid());
+		}
+		Ok(name)
+	}
+	/// Reads a quoted literal and returns its content.
+	fn read_literal(&mut self) -> ExtractResult<String> {
+		let quote = match self.peek() {
+			Some(c) if c.is_quote() => c,
+			_ => return Err(self.invalid()),
+		};
+		let start = self.position;
+		self.next();
+		let mut value = String::new();
+		loop {
+			match self.next() {
+				Some(c) if c == quote => return Ok(value),
+				Some(c) => value.push(c),
+				None => return Err(ExtractError::UnterminatedValue(quote, start)),
+			}
+		}
+	}
+	/// ExternalID, with `public_only` the system literal may be left out as in notations.
+	fn read_external_id(&mut self, public_only: bool) -> ExtractResult<XmlExternalId> {
+		let position = self.position;
+		let keyword = self.read_name()?;
+		match keyword.as_str() {
+			"SYSTEM" => {
+				self.expect_whitespace()?;
+				Ok(XmlExternalId::System(self.read_literal()?))
+			},
+			"PUBLIC" => {
+				self.expect_whitespace()?;
+				let public = self.read_literal()?;
+				let separated = self.skip_whitespace();
+				match self.peek() {
+					Some(c) if c.is_quote() && separated => Ok(XmlExternalId::Public(public, Some(self.read_literal()?))),
+					_ if public_only => Ok(XmlExternalId::Public(public, None)),
+					_ => Err(self.invalid()),
+				}
+			},
+			_ => Err(ExtractError::InvalidDoctype(keyword, position)),
+		}
+	}
+	fn read_internal_subset(&mut self) -> ExtractResult<Vec<XmlMarkupDeclaration>> {
+		let mut declarations = vec![];
+		loop {
+			self.skip_whitespace();
+			match self.peek() {
+				Some(']') => {
+					self.next();
+					return Ok(declarations);
+				},
+				Some('%') => {
+					self.next();
+					let name = self.read_name()?;
+					self.expect(";")?;
+					declarations.push(XmlMarkupDeclaration::ParameterEntityReference(name));
+				},
+				Some('<') => {
+					self.next();
+					if let Some(declaration) = self.read_markup_declaration()? {
+						declarations.push(declaration);
+					}
+				},
+				Some(_) => return Err(self.invalid()),
+				None => return Err(ExtractError::UnterminatedDoctype(self.position)),
+			}
+		}
+	}
+	/// Reads a declaration following `<`, `None` for comments and processing instructions.
+	fn read_markup_declaration(&mut self) -> ExtractResult<Option<XmlMarkupDeclaration>> {
+		if self.peek() == Some('?') {
+			self.skip_past("?>")?;
+			return Ok(None);
+		}
+		self.expect("!")?;
+		if self.peek() == Some('-') {
+			self.expect("--")?;
+			self.skip_past("-->")?;
+			return Ok(None);
+		}
+		let position = self.position;
+		let keyword = self.read_name()?;
+		self.expect_whitespace()?;
+		let declaration = match keyword.as_str() {
+			"ELEMENT" => XmlMarkupDeclaration::Element(self.read_element_declaration()?),
+			"ATTLIST" => XmlMarkupDeclaration::AttributeList(self.read_attribute_list_declaration()?),
+			"ENTITY" => XmlMarkupDeclaration::Entity(self.read_entity_declaration()?),
+			"NOTATION" => XmlMarkupDeclaration::Notation(self.read_notation_declaration()?),
+			_ => return Err(ExtractError::InvalidDoctype(keyword, position)),
+		};
+		self.skip_whitespace();
+		self.expect(">")?;
+		Ok(Some(declaration))
+	}
+	fn skip_past(&mut self, terminator: &str) -> ExtractResult<()> {
+		let mut text = String::new();
+		while let Some(c) = self.next() {
+			text.push(c);
+			if text.ends_with(terminator) {
+				return Ok(());
+			}
+		}
+		Err(ExtractError::UnterminatedDoctype(self.position))
+	}
+	fn read_element_declaration(&mut self) -> ExtractResult<XmlElementDeclaration> {
+		let name = self.read_name()?;
+		self.expect_whitespace()?;
+		let position = self.position;
+		let content = match self.peek() {
+			Some('(') => self.read_content_model()?,
+			_ => match self.read_name()?.as_str() {
+				"EMPTY" => XmlContentSpec::Empty,
+				"ANY" => XmlContentSpec::Any,
+				other => return Err(ExtractError::InvalidDoctype(other.to_string(), position)),
+			},
+		};
+		Ok(XmlElementDeclaration { name, content })
+	}
+	/// Mixed content or element content, starting at the opening parenthesis.
+	fn read_content_model(&mut self) -> ExtractResult<XmlContentSpec> {
+		self.expect("(")?;
+		self.skip_whitespace();
+		if self.peek() != Some('#') {
+			return Ok(XmlContentSpec::Children(self.read_group()?));
+		}
+		self.expect("#PCDATA")?;
+		let mut names = vec![];
+		loop {
+			self.skip_whitespace();
+			match self.peek() {
+				Some('|') => {
+					self.next();
+					self.skip_whitespace();
+					names.push(self.read_name()?);
+				},
+				Some(')') => break,
+				_ => return Err(self.invalid()),
+			}
+		}
+		self.next();
+		// names require the group to repeat, (#PCDATA) may repeat as well
+		if self.peek() == Some('*') {
+			self.next();
+		}
+		else if !names.is_empty() {
+			return Err(self.invalid());
+		}
+		Ok(XmlContentSpec::Mixed(names))
+	}
+	/// Sequence or choice after its opening parenthesis, with its repetition.
+	fn read_group(&mut self) -> ExtractResult<XmlContentParticle> {
+		self.depth += 1;
+		let mut particles = vec![self.read_particle()?];
+		let mut separator = None;
+		loop {
+			self.skip_whitespace();
+			match self.peek() {
+				Some(')') => break,
+				Some(c) if (c == ',' || c == '|') && separator.unwrap_or(c) == c => {
+					separator = Some(c);
+					self.next();
+					self.skip_whitespace();
+					particles.push(self.read_particle()?);
+				},
+				_ => return Err(self.invalid()),
+			}
+		}
+		self.next();
+		self.depth -= 1;
+		let kind = match separator {
+			Some('|') => XmlParticleKind::Choice(particles),
+			_ => XmlParticleKind::Sequence(particles),
+		};
+		Ok(XmlContentParticle::new(kind, self.read_repetition()))
+	}
+	fn read_particle(&mut self) -> ExtractResult<XmlContentParticle> {
+		if self.peek() == Some('(') {
+			if self.depth >= MAX_GROUP_DEPTH {
+				return Err(self.invalid());
+			}
+			self.next();
+			self.skip_whitespace();
+			return self.read_group();
+		}
+		let name = self.read_name()?;
+		Ok(XmlContentParticle::new(XmlParticleKind::Name(name), self.read_repetition()))
+	}
+	fn read_repetition(&mut self) -> XmlRepetition {
+		let repetition = match self.peek() {
+			Some('?') => XmlRepetition::Optional,
+			Some('*') => XmlRepetition::ZeroOrMore,
+			Some('+') => XmlRepetition::OneOrMore,
+			_ => return XmlRepetition::One,
+		};
+		self.next();
+		repetition
+	}
+	fn read_attribute_list_declaration(&mut self) -> ExtractResult<XmlAttributeListDeclaration> {
+		let element = self.read_name()?;
+		let mut attributes = vec![];
+		loop {
+			let separated = self.skip_whitespace();
+			match self.peek() {
+				Some('>') => break,
+				_ if !separated => return Err(self.invalid()),
+				_ => attributes.push(self.read_attribute_definition()?),
+			}
+		}
+		Ok(XmlAttributeListDeclaration { element, attributes })
+	}
+	fn read_attribute_definition(&mut self) -> ExtractResult<XmlAttributeDefinition> {
+		let name = self.read_name()?;
+		self.expect_whitespace()?;
+		let kind = if self.peek() == Some('(') {
+			XmlAttributeType::Enumeration(self.read_enumeration(true)?)
+		}
+		else {
+			let position = self.position;
+			match self.read_name()?.as_str() {
+				"CDATA" => XmlAttributeType::CData,
+				"ID" => XmlAttributeType::Id,
+				"IDREF" => XmlAttributeType::IdRef,
+				"IDREFS" => XmlAttributeType::IdRefs,
+				"ENTITY" => XmlAttributeType::Entity,
+				"ENTITIES" => XmlAttributeType::Entities,
+				"NMTOKEN" => XmlAttributeType::NmToken,
+				"NMTOKENS" => XmlAttributeType::NmTokens,
+				"NOTATION" => {
+					self.expect_whitespace()?;
+					XmlAttributeType::Notation(self.read_enumeration(false)?)
+				},
+				other => return Err(ExtractError::InvalidDoctype(other.to_string(), position)),
+			}
+		};
+		self.expect_whitespace()?;
+		let default = if self.peek() == Some('#') {
+			self.next();
+			let position = self.position;
+			match self.read_name()?.as_str() {
+				"REQUIRED" => XmlAttributeDefault::Required,
+				"IMPLIED" => XmlAttributeDefault::Implied,
+				"FIXED" => {
+					self.expect_whitespace()?;
+					XmlAttributeDefault::Fixed(self.read_attribute_value()?)
+				},
+				other => return Err(ExtractError::InvalidDoctype(other.to_string(), position)),
+			}
+		}
+		else {
+			XmlAttributeDefault::Value(self.read_attribute_value()?)
+		};
+		Ok(XmlAttributeDefinition { name, kind, default })
+	}
+	/// `(a|b)`, with `tokens` the values are Nmtokens instead of names.
+	fn read_enumeration(&mut self, tokens: bool) -> ExtractResult<Vec<String>> {
+		self.expect("(")?;
+		let mut values = vec![];
+		loop {
+			self.skip_whitespace();
+			values.push(if tokens { self.read_name_token()? } else { self.read_name()? });
+			self.skip_whitespace();
+			match self.peek() {
+				Some('|') => self.next(),
+				Some(')') => {
+					self.next();
+					return Ok(values);
+				},
+				_ => return Err(self.invalid()),
+			};
+		}
+	}
+	fn read_attribute_value(&mut self) -> ExtractResult<String> {
+		let position = self.position;
+		let value = self.read_literal()?;
+		entity::decode(&value, position)
+	}
+	fn read_entity_declaration(&mut self) -> ExtractResult<XmlEntityDeclaration> {
+		let parameter = self.peek() == Some('%');
+		if parameter {
+			self.next();
+			self.expect_whitespace()?;
+		}
+		let name = self.read_name()?;
+		self.expect_whitespace()?;
+		let value = match self.peek() {
+			Some(c) if c.is_quote() => XmlEntityValue::Internal(self.read_literal()?),
+			_ => {
+				let external_id = self.read_external_id(false)?;
+				let separated = self.skip_whitespace();
+				let mut notation = None;
+				if !parameter && separated && self.peek() == Some('N') {
+					self.expect("NDATA")?;
+					self.expect_whitespace()?;
+					notation = Some(self.read_name()?);
+				}
+				XmlEntityValue::External(external_id, notation)
+			},
+		};
+		Ok(XmlEntityDeclaration { name, parameter, value })
+	}
+	fn read_notation_declaration(&mut self) -> ExtractResult<XmlNotationDeclaration> {
+		let name = self.read_name()?;
+		self.expect_whitespace()?;
+		let external_id = self.read_external_id(true)?;
+		Ok(XmlNotationDeclaration { name, external_id })
+	}
+}
+
+
+/// Writes the literal in quotes that do not occur in it.
+fn write_literal(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+	if value.contains('"') {
+		write!(f, "'{}'", value)
+	}
+	else {
+		write!(f, "\"{}\"", value)
+	}
+}
+
+
+impl fmt::Display for XmlDoctype {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name)?;
+		if let Some(ref external_id) = self.external_id {
+			write!(f, " {}", external_id)?;
+		}
+		if !self.declarations.is_empty() {
+			write!(f, " [")?;
+			for declaration in &self.declarations {
+				write!(f, "{}", declaration)?;
+			}
+			write!(f, "]")?;
+		}
+		Ok(())
+	}
+}
+
+
+impl fmt::Display for XmlExternalId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlExternalId::System(system) => {
+				write!(f, "SYSTEM ")?;
+				write_literal(f, system)
+			},
+			XmlExternalId::Public(public, system) => {
+				write!(f, "PUBLIC ")?;
+				write_literal(f, public)?;
+				if let Some(system) = system {
+					write!(f, " ")?;
+					write_literal(f, system)?;
+				}
+				Ok(())
+			},
+		}
+	}
+}
+
+
+impl fmt::Display for XmlMarkupDeclaration {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlMarkupDeclaration::Element(element) => write!(f, "<!ELEMENT {} {}>", element.name, element.content),
+			XmlMarkupDeclaration::AttributeList(list) => {
+				write!(f, "<!ATTLIST {}", list.element)?;
+				for attribute in &list.attributes {
+					write!(f, " {} {} {}", attribute.name, attribute.kind, attribute.default)?;
+				}
+				write!(f, ">")
+			},
+			XmlMarkupDeclaration::Entity(entity) => {
+				write!(f, "<!ENTITY {}{} ", if entity.parameter { "% " } else { "" }, entity.name)?;
+				match entity.value {
+					XmlEntityValue::Internal(ref value) => write_literal(f, value)?,
+					XmlEntityValue::External(ref external_id, None) => write!(f, "{}", external_id)?,
+					XmlEntityValue::External(ref external_id, Some(ref notation)) => write!(f, "{} NDATA {}", external_id, notation)?,
+				}
+				write!(f, ">")
+			},
+			XmlMarkupDeclaration::Notation(notation) => write!(f, "<!NOTATION {} {}>", notation.name, notation.external_id),
+			XmlMarkupDeclaration::ParameterEntityReference(name) => write!(f, "%{};", name),
+		}
+	}
+}
+
+
+impl fmt::Display for XmlContentSpec {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlContentSpec::Empty => write!(f, "EMPTY"),
+			XmlContentSpec::Any => write!(f, "ANY"),
+			XmlContentSpec::Mixed(names) if names.is_empty() => write!(f, "(#PCDATA)"),
+			XmlContentSpec::Mixed(names) => write!(f, "(#PCDATA|{})*", names.join("|")),
+			XmlContentSpec::Children(particle) => write!(f, "{}", particle),
+		}
+	}
+}
+
+
+impl fmt::Display for XmlContentParticle {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let join = |f: &mut fmt::Formatter, particles: &[XmlContentParticle], separator: &str| -> fmt::Result {
+			write!(f, "(")?;
+			for (index, particle) in particles.iter().enumerate() {
+				if index > 0 {
+					write!(f, "{}", separator)?;
+				}
+				write!(f, "{}", particle)?;
+			}
+			write!(f, ")")
+		};
+		match self.kind {
+			XmlParticleKind::Name(ref name) => write!(f, "{}", name)?,
+			XmlParticleKind::Sequence(ref particles) => join(f, particles, ",")?,
+			XmlParticleKind::Choice(ref particles) => join(f, particles, "|")?,
+		}
+		write!(f, "{}", self.repetition)
+	}
+}
+
+
+impl fmt::Display for XmlRepetition {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlRepetition::One => Ok(()),
+			XmlRepetition::Optional => write!(f, "?"),
+			XmlRepetition::ZeroOrMore => write!(f, "*"),
+			XmlRepetition::OneOrMore => write!(f, "+"),
+		}
+	}
+}
+
+
+impl fmt::Display for XmlAttributeType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlAttributeType::CData => write!(f, "CDATA"),
+			XmlAttributeType::Id => write!(f, "ID"),
+			XmlAttributeType::IdRef => write!(f, "IDREF"),
+			XmlAttributeType::IdRefs => write!(f, "IDREFS"),
+			XmlAttributeType::Entity => write!(f, "ENTITY"),
+			XmlAttributeType::Entities => write!(f, "ENTITIES"),
+			XmlAttributeType::NmToken => write!(f, "NMTOKEN"),
+			XmlAttributeType::NmTokens => write!(f, "NMTOKENS"),
+			XmlAttributeType::Notation(names) => write!(f, "NOTATION ({})", names.join("|")),
+			XmlAttributeType::Enumeration(values) => write!(f, "({})", values.join("|")),
+		}
+	}
+}
+
+
+impl fmt::Display for XmlAttributeDefault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlAttributeDefault::Required => write!(f, "#REQUIRED"),
+			XmlAttributeDefault::Implied => write!(f, "#IMPLIED"),
+			XmlAttributeDefault::Fixed(value) => write!(f, "#FIXED \"{}\"", entity::escape(value)),
+			XmlAttributeDefault::Value(value) => write!(f, "\"{}\"", entity::escape(value)),
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use errors::ExtractError;
+	use span::XmlPosition;
+	use super::*;
+
+	fn name(name: &str, repetition: XmlRepetition) -> XmlContentParticle {
+		XmlContentParticle::new(XmlParticleKind::Name(name.to_string()), repetition)
+	}
+
+	#[test]
+	fn parse_name_only() {
+		let doctype = XmlDoctype::parse(" html", XmlPosition::start()).unwrap();
+		assert_eq!(doctype, XmlDoctype::new("html".to_string(), None, vec![]));
+	}
+	#[test]
+	fn parse_external_ids() {
+		let doctype = XmlDoctype::parse(" root SYSTEM \"root.dtd\"", XmlPosition::start()).unwrap();
+		assert_eq!(doctype.external_id, Some(XmlExternalId::System("root.dtd".to_string())));
+		let doctype = XmlDoctype::parse(" html PUBLIC '-//W3C//DTD XHTML 1.0 Strict//EN' 'xhtml1-strict.dtd' ", XmlPosition::start()).unwrap();
+		assert_eq!(doctype.external_id, Some(XmlExternalId::Public("-//W3C//DTD XHTML 1.0 Strict//EN".to_string(), Some("xhtml1-strict.dtd".to_string()))));
+	}
+	#[test]
+	fn parse_element_declarations() {
+		let doctype = XmlDoctype::parse(" a [
+			<!ELEMENT a (b, (c | d)*, e?)+>
+			<!ELEMENT b (#PCDATA)>
+			<!ELEMENT c (#PCDATA | i | em)*>
+			<!ELEMENT d EMPTY>
+			<!ELEMENT e ANY>
+		]", XmlPosition::start()).unwrap();
+		let elements: Vec<&XmlElementDeclaration> = doctype.elements().collect();
+		assert_eq!(elements.len(), 5);
+		assert_eq!(elements[0].content, XmlContentSpec::Children(XmlContentParticle::new(XmlParticleKind::Sequence(vec![
+			name("b", XmlRepetition::One),
+			XmlContentParticle::new(XmlParticleKind::Choice(vec![name("c", XmlRepetition::One), name("d", XmlRepetition::One)]), XmlRepetition::ZeroOrMore),
+			name("e", XmlRepetition::Optional),
+		]), XmlRepetition::OneOrMore)));
+		assert_eq!(elements[1].content, XmlContentSpec::Mixed(vec![]));
+		assert_eq!(elements[2].content, XmlContentSpec::Mixed(vec!["i".to_string(), "em".to_string()]));
+		assert_eq!(doctype.element("d").unwrap().content, XmlContentSpec::Empty);
+		assert_eq!(doctype.element("e").unwrap().content, XmlContentSpec::Any);
+		assert_eq!(elements[0].content.to_string(), "(b,(c|d)*,e?)+".to_string());
+	}
+	#[test]
+	fn parse_nested_groups() {
+		let nested = |depth: usize| format!(" a [<!ELEMENT a {}b{}>]", "(".repeat(depth), ")".repeat(depth));
+		assert!(XmlDoctype::parse(&nested(MAX_GROUP_DEPTH), XmlPosition::start()).is_ok());
+		match XmlDoctype::parse(&nested(MAX_GROUP_DEPTH + 1), XmlPosition::start()) {
+			Err(ExtractError::InvalidDoctype(text, position)) => {
+				assert!(text.starts_with('('));
+				assert_eq!(position.offset, 16 + MAX_GROUP_DEPTH);
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!(XmlDoctype::parse(&nested(200_000), XmlPosition::start()), Err(ExtractError::InvalidDoctype(_, _))));
+	}
+	#[test]
+	fn parse_attribute_list_declaration() {
+		let doctype = XmlDoctype::parse(" a [<!ATTLIST a
+			id ID #REQUIRED
+			ref IDREF #IMPLIED
+			size (small | medium | 1x) 'medium'
+			version CDATA #FIXED \"1.0 &amp; up\"
+			type NOTATION (gif|png) #IMPLIED>]", XmlPosition::start()).unwrap();
+		let list = doctype.attribute_lists().next().unwrap();
+		assert_eq!(list.element, "a");
+		assert_eq!(list.attributes.len(), 5);
+		assert_eq!(list.attributes[0], XmlAttributeDefinition { name: "id".to_string(), kind: XmlAttributeType::Id, default: XmlAttributeDefault::Required });
+		assert_eq!(list.attributes[1].kind, XmlAttributeType::IdRef);
+		assert_eq!(list.attributes[2].kind, XmlAttributeType::Enumeration(vec!["small".to_string(), "medium".to_string(), "1x".to_string()]));
+		assert_eq!(list.attributes[2].default, XmlAttributeDefault::Value("medium".to_string()));
+		assert_eq!(list.attributes[3].default, XmlAttributeDefault::Fixed("1.0 & up".to_string()));
+		assert_eq!(list.attributes[4].kind, XmlAttributeType::Notation(vec!["gif".to_string(), "png".to_string()]));
+	}
+	#[test]
+	fn parse_entity_and_notation_declarations() {
+		let doctype = XmlDoctype::parse(" a SYSTEM 'a.dtd' [
+			<!-- comments and <?pi?> are skipped, even with ' and > -->
+			<?pi data?>
+			<!ENTITY copy \"&#169; 2024\">
+			<!ENTITY % common SYSTEM \"common.ent\">
+			%common;
+			<!ENTITY logo SYSTEM \"logo.gif\" NDATA gif>
+			<!NOTATION gif PUBLIC \"image/gif\">
+		] ", XmlPosition::start()).unwrap();
+		assert_eq!(doctype.declarations.len(), 5);
+		assert_eq!(doctype.entity("copy").unwrap().value, XmlEntityValue::Internal("&#169; 2024".to_string()));
+		let common = doctype.entities().nth(1).unwrap();
+		assert!(common.parameter);
+		assert_eq!(common.value, XmlEntityValue::External(XmlExternalId::System("common.ent".to_string()), None));
+		assert!(doctype.entity("common").is_none());
+		assert_eq!(doctype.declarations[2], XmlMarkupDeclaration::ParameterEntityReference("common".to_string()));
+		assert_eq!(doctype.entity("logo").unwrap().value, XmlEntityValue::External(XmlExternalId::System("logo.gif".to_string()), Some("gif".to_string())));
+		assert_eq!(doctype.notations().next().unwrap().external_id, XmlExternalId::Public("image/gif".to_string(), None));
+	}
+	#[test]
+	fn parse_invalid_doctype() {
+		let invalid = |text: &str| match XmlDoctype::parse(text, XmlPosition::start()) {
+			Err(ExtractError::InvalidDoctype(text, position)) => (text, position.offset),
+			other => panic!("unexpected result {:?}", other),
+		};
+		assert_eq!(invalid("html"), ("html".to_string(), 0));
+		assert_eq!(invalid(" 1html"), ("1html".to_string(), 1));
+		assert_eq!(invalid(" a LOCAL \"a.dtd\""), ("LOCAL".to_string(), 3));
+		assert_eq!(invalid(" a [<!ELEMENT a (b, c | d)>]"), ("|".to_string(), 22));
+		assert_eq!(invalid(" a [<!ATTLIST a b (x y) #IMPLIED>]"), ("y".to_string(), 21));
+		assert_eq!(invalid(" a [<!ELEMENT a (#PCDATA | b)>]"), (">".to_string(), 29));
+		assert_eq!(invalid(" a [<!ATTLIST a b NUMBER #IMPLIED>]"), ("NUMBER".to_string(), 18));
+		assert_eq!(invalid(" a [<!ENTITY a SYSTEM>]"), (">".to_string(), 21));
+		assert_eq!(invalid(" a [<!DOCTYPE a>]"), ("DOCTYPE".to_string(), 6));
+		assert!(matches!(XmlDoctype::parse(" a [", XmlPosition::start()), Err(ExtractError::UnterminatedDoctype(_))));
+	}
+	#[test]
+	fn display_doctype() {
+		let text = " a SYSTEM \"a.dtd\" [<!ELEMENT a (#PCDATA|b)*><!ATTLIST a id ID #REQUIRED size (s|m) \"m\"><!ENTITY e 'it\"s'><!NOTATION n PUBLIC \"p\">]";
+		let doctype = XmlDoctype::parse(text, XmlPosition::start()).unwrap();
+		assert_eq!(format!(" {}", doctype), text.to_string());
+		assert_eq!(XmlDoctype::parse(&format!(" {}", doctype), XmlPosition::start()).unwrap(), doctype);
+	}
+}
@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str;
+use declaration::XmlDeclaration;
+use errors::ExtractError;
+use errors::ExtractResult;
+use span::XmlPosition;
+
+
+/// Encodings a document can be read from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XmlEncoding {
+	Utf8,
+	Ascii,
+	Utf16LE,
+	Utf16BE,
+	Latin1,
+}
+
+
+impl XmlEncoding {
+	/// Encoding for a name used in the `encoding` of the XML declaration,
+	/// `None` when it is not supported. `UTF-16` without byte order is big endian.
+	pub fn from_label(label: &str) -> Option<XmlEncoding> {
+		match label.to_ascii_uppercase().as_str() {
+			"UTF-8" | "UTF8" => Some(XmlEncoding::Utf8),
+			"US-ASCII" | "ASCII" => Some(XmlEncoding::Ascii),
+			"UTF-16" | "UTF-16BE" => Some(XmlEncoding::Utf16BE),
+			"UTF-16LE" => Some(XmlEncoding::Utf16LE),
+			"ISO-8859-1" | "ISO_8859-1" | "LATIN1" | "LATIN-1" | "L1" => Some(XmlEncoding::Latin1),
+			_ => None,
+		}
+	}
+	pub fn name(&self) -> &'static str {
+		match *self {
+			XmlEncoding::Utf8 => "UTF-8",
+			XmlEncoding::Ascii => "US-ASCII",
+			XmlEncoding::Utf16LE => "UTF-16LE",
+			XmlEncoding::Utf16BE => "UTF-16BE",
+			XmlEncoding::Latin1 => "ISO-8859-1",
+		}
+	}
+	pub fn is_utf16(&self) -> bool {
+		matches!(*self, XmlEncoding::Utf16LE | XmlEncoding::Utf16BE)
+	}
+}
+
+
+impl fmt::Display for XmlEncoding {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+
+/// Encoding given away by a byte order mark or the first characters of the
+/// document, with the length of the byte order mark. `None` means the input
+/// is compatible with ASCII and the declaration decides.
+pub fn detect(bytes: &[u8]) -> ExtractResult<(Option<XmlEncoding>, usize)> {
+	match bytes {
+		[0xEF, 0xBB, 0xBF, ..] => Ok((Some(XmlEncoding::Utf8), 3)),
+		[0x00, 0x00, 0xFE, 0xFF, ..] | [0xFF, 0xFE, 0x00, 0x00, ..] |
+		[0x00, 0x00, 0x00, 0x3C, ..] | [0x3C, 0x00, 0x00, 0x00, ..] => {
+			Err(ExtractError::UnsupportedEncoding("UCS-4".to_string(), XmlPosition::start()))
+		},
+		[0xFF, 0xFE, ..] => Ok((Some(XmlEncoding::Utf16LE), 2)),
+		[0xFE, 0xFF, ..] => Ok((Some(XmlEncoding::Utf16BE), 2)),
+		[0x3C, 0x00, 0x3F, 0x00, ..] => Ok((Some(XmlEncoding::Utf16LE), 0)),
+		[0x00, 0x3C, 0x00, 0x3F, ..] => Ok((Some(XmlEncoding::Utf16BE), 0)),
+		_ => Ok((None, 0)),
+	}
+}
+
+
+/// Decodes a document to UTF-8, using the byte order mark and the encoding
+/// named in the XML declaration. The byte order mark is not part of the result,
+/// UTF-8 input is borrowed.
+pub fn decode(bytes: &[u8]) -> ExtractResult<Cow<'_, str>> {
+	let (detected, bom) = detect(bytes)?;
+	let bytes = &bytes[bom..];
+	match detected {
+		Some(encoding) => {
+			let text = decode_as(bytes, encoding)?;
+			if let Some(label) = declared_encoding(&text)? {
+				resolve(&label, detected)?;
+			}
+			Ok(text)
+		},
+		None => {
+			// the declaration is ASCII, read it before deciding on the rest
+			let encoding = match declared_encoding(decode_declaration(bytes)?)? {
+				Some(label) => resolve(&label, None)?,
+				None => XmlEncoding::Utf8,
+			};
+			decode_as(bytes, encoding)
+		},
+	}
+}
+
+
+/// Decodes the bytes, which must not start with a byte order mark.
+pub fn decode_as(bytes: &[u8], encoding: XmlEncoding) -> ExtractResult<Cow<'_, str>> {
+	match encoding {
+		XmlEncoding::Utf8 => decode_utf8(bytes).map(Cow::Borrowed),
+		XmlEncoding::Ascii => decode_ascii(bytes).map(Cow::Borrowed),
+		XmlEncoding::Utf16LE => decode_utf16(bytes, u16::from_le_bytes).map(Cow::Owned),
+		XmlEncoding::Utf16BE => decode_utf16(bytes, u16::from_be_bytes).map(Cow::Owned),
+		XmlEncoding::Latin1 => Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect())),
+	}
+}
+
+
+fn decode_utf8(bytes: &[u8]) -> ExtractResult<&str> {
+	match str::from_utf8(bytes) {
+		Ok(text) => Ok(text),
+		Err(e) => {
+			let valid = str::from_utf8(&bytes[..e.valid_up_to()]).unwrap();
+			Err(ExtractError::InvalidUtf8(bytes[e.valid_up_to()], end_of(valid)))
+		},
+	}
+}
+
+
+fn decode_ascii(bytes: &[u8]) -> ExtractResult<&str> {
+	match bytes.iter().position(|b| !b.is_ascii()) {
+		Some(index) => Err(ExtractError::InvalidAscii(bytes[index], end_of(str::from_utf8(&bytes[..index]).unwrap()))),
+		None => Ok(str::from_utf8(bytes).unwrap()),
+	}
+}
+
+
+// `is_multiple_of` would need Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> ExtractResult<String> {
+	let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+	let mut text = String::with_capacity(bytes.len() / 2);
+	for c in char::decode_utf16(units) {
+		match c {
+			Ok(c) => text.push(c),
+			Err(e) => return Err(ExtractError::InvalidUtf16(e.unpaired_surrogate(), end_of(&text))),
+		}
+	}
+	if bytes.len() % 2 != 0 {
+		return Err(ExtractError::UnexpectedEnd(end_of(&text)));
+	}
+	Ok(text)
+}
+
+
+fn end_of(text: &str) -> XmlPosition {
+	let mut position = XmlPosition::start();
+	for c in text.chars() {
+		position.advance(c);
+	}
+	position
+}
+
+
+/// The XML declaration at the start of ASCII compatible input, decoded on its own.
+fn decode_declaration(bytes: &[u8]) -> ExtractResult<&str> {
+	if !bytes.starts_with(b"<?xml") {
+		return Ok("");
+	}
+	let length = bytes.windows(2).position(|pair| pair == b"?>").map_or(bytes.len(), |index| index + 2);
+	decode_utf8(&bytes[..length])
+}
+
+
+/// Value of `encoding` in the declaration the text starts with, if any.
+fn declared_encoding(text: &str) -> ExtractResult<Option<String>> {
+	if !text.starts_with("<?xml") || !text[5..].starts_with(|c: char| c.is_ascii_whitespace() || c == '?') {
+		return Ok(None);
+	}
+	let data = match text.find("?>") {
+		Some(end) => &text[5..end],
+		None => return Err(ExtractError::UnterminatedProcessingInstruction(XmlPosition::start())),
+	};
+	Ok(XmlDeclaration::parse(data, XmlPosition::start())?.encoding)
+}
+
+
+/// Checks the declared encoding against the detected one and returns the
+/// encoding to decode with.
+fn resolve(label: &str, detected: Option<XmlEncoding>) -> ExtractResult<XmlEncoding> {
+	let position = XmlPosition::start();
+	let declared = XmlEncoding::from_label(label).ok_or_else(|| ExtractError::UnsupportedEncoding(label.to_string(), position))?;
+	match detected {
+		None if !declared.is_utf16() => Ok(declared),
+		Some(detected) if detected == declared || (detected.is_utf16() && label.eq_ignore_ascii_case("UTF-16")) => Ok(detected),
+		_ => {
+			let detected = detected.unwrap_or(XmlEncoding::Utf8);
+			Err(ExtractError::EncodingMismatch(label.to_string(), detected.name().to_string(), position))
+		},
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use errors::ExtractError;
+	use span::XmlPosition;
+	use super::*;
+
+	fn utf16le(text: &str) -> Vec<u8> {
+		text.encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()).collect()
+	}
+	fn utf16be(text: &str) -> Vec<u8> {
+		text.encode_utf16().flat_map(|unit| unit.to_be_bytes().to_vec()).collect()
+	}
+
+	#[test]
+	fn encoding_labels() {
+		assert_eq!(XmlEncoding::from_label("utf-8"), Some(XmlEncoding::Utf8));
+		assert_eq!(XmlEncoding::from_label("UTF-16"), Some(XmlEncoding::Utf16BE));
+		assert_eq!(XmlEncoding::from_label("us-ascii"), Some(XmlEncoding::Ascii));
+		assert_eq!(XmlEncoding::from_label("iso-8859-1"), Some(XmlEncoding::Latin1));
+		assert_eq!(XmlEncoding::from_label("Shift_JIS"), None);
+	}
+	#[test]
+	fn detect_encoding() {
+		assert_eq!(detect(b"\xEF\xBB\xBF<a/>").unwrap(), (Some(XmlEncoding::Utf8), 3));
+		assert_eq!(detect(b"\xFF\xFE<\x00").unwrap(), (Some(XmlEncoding::Utf16LE), 2));
+		assert_eq!(detect(b"\xFE\xFF\x00<").unwrap(), (Some(XmlEncoding::Utf16BE), 2));
+		assert_eq!(detect(b"<\x00?\x00x\x00").unwrap(), (Some(XmlEncoding::Utf16LE), 0));
+		assert_eq!(detect(b"<a/>").unwrap(), (None, 0));
+		assert!(matches!(detect(b"\x00\x00\x00<"), Err(ExtractError::UnsupportedEncoding(_, _))));
+	}
+	#[test]
+	fn decode_utf8_with_bom() {
+		assert_eq!(decode("\u{FEFF}<?xml version=\"1.0\"?><a>ä</a>".as_bytes()).unwrap(), "<?xml version=\"1.0\"?><a>ä</a>".to_string());
+	}
+	#[test]
+	fn decode_utf16() {
+		let text = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><a>€𝄞</a>";
+		let mut bytes = vec![0xFF, 0xFE];
+		bytes.extend(utf16le(text));
+		assert_eq!(decode(&bytes).unwrap(), text.to_string());
+		let mut bytes = vec![0xFE, 0xFF];
+		bytes.extend(utf16be(text));
+		assert_eq!(decode(&bytes).unwrap(), text.to_string());
+		assert_eq!(decode(&utf16le("<?xml version=\"1.0\"?><a/>")).unwrap(), "<?xml version=\"1.0\"?><a/>".to_string());
+	}
+	#[test]
+	fn decode_latin1() {
+		let bytes = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>gr\xF6\xDFe</a>";
+		assert_eq!(decode(bytes).unwrap(), "<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>größe</a>".to_string());
+	}
+	#[test]
+	fn decode_ascii() {
+		let text = "<?xml version=\"1.0\" encoding=\"US-ASCII\"?><a>b</a>";
+		assert_eq!(decode(text.as_bytes()).unwrap(), text.to_string());
+		match decode(b"<?xml version=\"1.0\" encoding=\"ASCII\"?>\n<a>\xC3\xA4</a>") {
+			Err(ExtractError::InvalidAscii(byte, position)) => {
+				assert_eq!(byte, 0xC3);
+				assert_eq!(position, XmlPosition::new(42, 2, 4));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn decode_errors() {
+		match decode(b"<?xml version=\"1.0\" encoding=\"EBCDIC\"?><a/>") {
+			Err(ExtractError::UnsupportedEncoding(label, _)) => assert_eq!(label, "EBCDIC"),
+			other => panic!("unexpected result {:?}", other),
+		}
+		match decode(b"\xEF\xBB\xBF<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a/>") {
+			Err(ExtractError::EncodingMismatch(declared, detected, _)) => {
+				assert_eq!(declared, "ISO-8859-1");
+				assert_eq!(detected, "UTF-8");
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!(decode(b"<?xml version=\"1.0\" encoding=\"UTF-16\"?><a/>"), Err(ExtractError::EncodingMismatch(_, _, _))));
+		match decode(b"<a>\n\xFF</a>") {
+			Err(ExtractError::InvalidUtf8(byte, position)) => {
+				assert_eq!(byte, 0xFF);
+				assert_eq!(position, XmlPosition::new(4, 2, 1));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!(decode(b"\xFF\xFE<\x00\x00\xD8"), Err(ExtractError::InvalidUtf16(0xD800, _))));
+		assert!(matches!(decode(b"\xFF\xFE<\x00a"), Err(ExtractError::UnexpectedEnd(_))));
+	}
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fmt;
+use doctype::XmlDoctype;
+use doctype::XmlEntityValue;
+use errors::ExtractError;
+use errors::ExtractResult;
+use options::XmlEntityLimits;
+use span::XmlPosition;
+use token::QuoteKind;
+use tokenizer::XmlValidation;
+
+
+/// Replacement of a predefined entity, `None` for any other name.
+pub fn predefined_entity(name: &str) -> Option<char> {
+	match name {
+		"lt" => Some('<'),
+		"gt" => Some('>'),
+		"amp" => Some('&'),
+		"apos" => Some('\''),
+		"quot" => Some('"'),
+		_ => None,
+	}
+}
+
+
+/// Character of a `#123` or `#x7B` character reference, `None` when the
+/// number is malformed or not an allowed XML character.
+pub fn character_reference(reference: &str) -> Option<char> {
+	let (digits, radix) = if let Some(digits) = reference.strip_prefix("#x") {
+		(digits, 16)
+	}
+	else if let Some(digits) = reference.strip_prefix('#') {
+		(digits, 10)
+	}
+	else {
+		return None;
+	};
+	// from_str_radix would also accept a leading sign
+	if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+		return None;
+	}
+	u32::from_str_radix(digits, radix).ok()
+		.and_then(::std::char::from_u32)
+		.filter(|c| c.is_valid_in_xml())
+}
+
+
+/// Decodes the reference between `&` and `;`, `position` is the position of
+/// the `&` and is used for errors.
+pub fn decode_reference(reference: &str, position: XmlPosition) -> ExtractResult<char> {
+	if reference.starts_with('#') {
+		character_reference(reference).ok_or_else(|| ExtractError::InvalidReference(reference.to_string(), position))
+	}
+	else {
+		predefined_entity(reference).ok_or_else(|| ExtractError::UnknownEntity(reference.to_string(), position))
+	}
+}
+
+
+/// Replaces all entity and character references in the text.
+pub fn decode(text: &str, position: XmlPosition) -> ExtractResult<String> {
+	let mut result = String::with_capacity(text.len());
+	let mut current = position;
+	let mut rest = text;
+	while let Some(index) = rest.find('&') {
+		result.push_str(&rest[..index]);
+		for c in rest[..index].chars() {
+			current.advance(c);
+		}
+		let end = rest[index..].find(';').ok_or_else(|| ExtractError::InvalidReference(rest[index + 1..].to_string(), current))?;
+		let reference = &rest[index + 1..index + end];
+		result.push(decode_reference(reference, current)?);
+		for c in rest[index..index + end + 1].chars() {
+			current.advance(c);
+		}
+		rest = &rest[index + end + 1..];
+	}
+	result.push_str(rest);
+	Ok(result)
+}
+
+
+/// The limit of `XmlEntityLimits` that an expansion ran into.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XmlEntityLimit {
+	Depth,
+	Size,
+	Expansions,
+}
+
+
+impl fmt::Display for XmlEntityLimit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			XmlEntityLimit::Depth => write!(f, "depth"),
+			XmlEntityLimit::Size => write!(f, "size"),
+			XmlEntityLimit::Expansions => write!(f, "expansions"),
+		}
+	}
+}
+
+
+/// Expands references to the internal entities of a DTD, keeping count of the
+/// replacement text over the whole document to stay within the limits.
+#[derive(Debug, Clone)]
+pub struct XmlEntityExpander {
+	entities: HashMap<String, Option<String>>, // replacement text of internal entities, None for external ones
+	limits: XmlEntityLimits,
+	size: usize,
+	expansions: usize,
+}
+
+
+impl XmlEntityExpander {
+	pub fn new(limits: XmlEntityLimits) -> XmlEntityExpander {
+		XmlEntityExpander {
+			entities: HashMap::new(),
+			limits,
+			size: 0,
+			expansions: 0,
+		}
+	}
+
+	/// Adds the general entities of the DTD, the first declaration of a name is binding.
+	pub fn declare(&mut self, doctype: &XmlDoctype) {
+		for entity in doctype.entities().filter(|entity| !entity.parameter) {
+			let replacement = match entity.value {
+				XmlEntityValue::Internal(ref value) => Some(value.clone()),
+				XmlEntityValue::External(_, _) => None,
+			};
+			self.entities.entry(entity.name.clone()).or_insert(replacement);
+		}
+	}
+
+	/// Replacement of the reference between `&` and `;`, `position` is the
+	/// position of the `&` and is used for errors.
+	pub fn expand(&mut self, reference: &str, position: XmlPosition) -> ExtractResult<String> {
+		if reference.starts_with('#') || predefined_entity(reference).is_some() || !self.entities.contains_key(reference) {
+			return decode_reference(reference, position).map(String::from);
+		}
+		let mut expanded = String::new();
+		self.expand_entity(reference, &mut vec![], &mut expanded, position)?;
+		self.size += expanded.len();
+		Ok(expanded)
+	}
+
+	fn expand_entity(&mut self, name: &str, open: &mut Vec<String>, expanded: &mut String, position: XmlPosition) -> ExtractResult<()> {
+		if open.iter().any(|entity| entity == name) {
+			return Err(ExtractError::RecursiveEntity(name.to_string(), position));
+		}
+		if open.len() >= self.limits.max_depth {
+			return Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Depth, position));
+		}
+		self.expansions += 1;
+		if self.expansions > self.limits.max_expansions {
+			return Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Expansions, position));
+		}
+		let replacement = match self.entities.get(name) {
+			Some(Some(replacement)) => replacement.clone(),
+			// external entities are not loaded
+			_ => return Err(ExtractError::UnknownEntity(name.to_string(), position)),
+		};
+		if replacement.contains('<') {
+			return Err(ExtractError::EntityMarkup(name.to_string(), position));
+		}
+		open.push(name.to_string());
+		let mut rest = replacement.as_str();
+		while let Some(index) = rest.find('&') {
+			self.append(expanded, &rest[..index], position)?;
+			let end = rest[index..].find(';').ok_or_else(|| ExtractError::InvalidReference(rest[index + 1..].to_string(), position))?;
+			let reference = &rest[index + 1..index + end];
+			if reference.starts_with('#') || predefined_entity(reference).is_some() {
+				let c = decode_reference(reference, position)?;
+				self.append(expanded, c.encode_utf8(&mut [0; 4]), position)?;
+			}
+			else {
+				self.expand_entity(reference, open, expanded, position)?;
+			}
+			rest = &rest[index + end + 1..];
+		}
+		self.append(expanded, rest, position)?;
+		open.pop();
+		Ok(())
+	}
+
+	fn append(&self, expanded: &mut String, text: &str, position: XmlPosition) -> ExtractResult<()> {
+		if self.size + expanded.len() + text.len() > self.limits.max_size {
+			return Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Size, position));
+		}
+		expanded.push_str(text);
+		Ok(())
+	}
+}
+
+
+/// Entity reference replacing the character when it is escaped.
+fn escaped_char(c: char) -> Option<&'static str> {
+	match c {
+		'<' => Some("&lt;"),
+		'>' => Some("&gt;"),
+		'&' => Some("&amp;"),
+		'\'' => Some("&apos;"),
+		'"' => Some("&quot;"),
+		_ => None,
+	}
+}
+
+
+fn escape_matching<F: Fn(char) -> bool>(text: &str, escaped: F) -> String {
+	let mut result = String::with_capacity(text.len());
+	for c in text.chars() {
+		match escaped_char(c) {
+			Some(reference) if escaped(c) => result.push_str(reference),
+			_ => result.push(c),
+		}
+	}
+	result
+}
+
+
+/// Escapes `&`, `<`, `>`, `"` and `'`, the result is safe anywhere in a document.
+pub fn escape(text: &str) -> String {
+	escape_matching(text, |_| true)
+}
+
+
+/// Escapes `&`, `<` and `>` for use as character data.
+pub fn escape_text(text: &str) -> String {
+	escape_matching(text, |c| c != '"' && c != '\'')
+}
+
+
+/// Escapes `&`, `<`, `>` and the quote delimiting the attribute value.
+pub fn escape_attribute(value: &str, quote: QuoteKind) -> String {
+	escape_matching(value, |c| !(c == '"' || c == '\'') || c == quote.to_char())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use doctype::XmlDoctype;
+	use errors::ExtractError;
+	use options::XmlEntityLimits;
+	use span::XmlPosition;
+	use token::QuoteKind;
+	use super::*;
+
+	fn expander(declarations: &str, limits: XmlEntityLimits) -> XmlEntityExpander {
+		let mut expander = XmlEntityExpander::new(limits);
+		expander.declare(&XmlDoctype::parse(&format!(" a [{}]", declarations), XmlPosition::start()).unwrap());
+		expander
+	}
+
+	#[test]
+	fn predefined_entities() {
+		assert_eq!(predefined_entity("lt"), Some('<'));
+		assert_eq!(predefined_entity("gt"), Some('>'));
+		assert_eq!(predefined_entity("amp"), Some('&'));
+		assert_eq!(predefined_entity("apos"), Some('\''));
+		assert_eq!(predefined_entity("quot"), Some('"'));
+		assert_eq!(predefined_entity("nbsp"), None);
+	}
+	#[test]
+	fn character_references() {
+		assert_eq!(character_reference("#x20AC"), Some('€'));
+		assert_eq!(character_reference("#8364"), Some('€'));
+		assert_eq!(character_reference("#x1F600"), Some('\u{1F600}'));
+		assert_eq!(character_reference("#x"), None);
+		assert_eq!(character_reference("#"), None);
+		assert_eq!(character_reference("#X20"), None);
+		assert_eq!(character_reference("#+32"), None);
+		assert_eq!(character_reference("#x+20"), None);
+		assert_eq!(character_reference("#0"), None);
+		assert_eq!(character_reference("#xD800"), None);
+		assert_eq!(character_reference("#x110000"), None);
+	}
+	#[test]
+	fn decode_text() {
+		assert_eq!(decode("a &lt;b&gt; &amp;&#x20AC;&#65;", XmlPosition::start()).unwrap(), "a <b> &€A".to_string());
+		assert_eq!(decode("plain", XmlPosition::start()).unwrap(), "plain".to_string());
+	}
+	#[test]
+	fn decode_errors() {
+		match decode("ab\n c &nbsp;", XmlPosition::start()) {
+			Err(ExtractError::UnknownEntity(name, position)) => {
+				assert_eq!(name, "nbsp");
+				assert_eq!(position, XmlPosition::new(6, 2, 4));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!(decode("&#xZZ;", XmlPosition::start()), Err(ExtractError::InvalidReference(_, _))));
+		assert!(matches!(decode("&amp", XmlPosition::start()), Err(ExtractError::InvalidReference(_, _))));
+	}
+	#[test]
+	fn expand_entities() {
+		let mut expander = expander("<!ENTITY a 'x&#x20AC;'><!ENTITY b '&a;&lt;&a;'><!ENTITY a 'ignored'>", XmlEntityLimits::default());
+		assert_eq!(expander.expand("b", XmlPosition::start()).unwrap(), "x€<x€".to_string());
+		assert_eq!(expander.expand("amp", XmlPosition::start()).unwrap(), "&".to_string());
+		assert!(matches!(expander.expand("c", XmlPosition::start()), Err(ExtractError::UnknownEntity(_, _))));
+	}
+	#[test]
+	fn expand_entity_errors() {
+		let mut expander = expander("<!ENTITY a '&b;'><!ENTITY b '&a;'><!ENTITY m '<b/>'>", XmlEntityLimits::default());
+		assert!(matches!(expander.expand("a", XmlPosition::start()), Err(ExtractError::RecursiveEntity(ref name, _)) if name == "a"));
+		assert!(matches!(expander.expand("m", XmlPosition::start()), Err(ExtractError::EntityMarkup(_, _))));
+		let limits = XmlEntityLimits { max_depth: 2, ..XmlEntityLimits::default() };
+		let mut expander = self::expander("<!ENTITY a 'a'><!ENTITY b '&a;'><!ENTITY c '&b;'>", limits);
+		assert_eq!(expander.expand("b", XmlPosition::start()).unwrap(), "a".to_string());
+		assert!(matches!(expander.expand("c", XmlPosition::start()), Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Depth, _))));
+	}
+	#[test]
+	fn expand_billion_laughs() {
+		let mut declarations = "<!ENTITY lol0 'lol'>".to_string();
+		for level in 1..10 {
+			let previous = format!("&lol{};", level - 1);
+			declarations.push_str(&format!("<!ENTITY lol{} '{}'>", level, previous.repeat(10)));
+		}
+		let mut limited = expander(&declarations, XmlEntityLimits::default());
+		assert!(matches!(limited.expand("lol9", XmlPosition::start()), Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Expansions, _))));
+		let limits = XmlEntityLimits { max_expansions: usize::MAX, ..XmlEntityLimits::default() };
+		let mut limited = expander(&declarations, limits);
+		assert!(matches!(limited.expand("lol9", XmlPosition::start()), Err(ExtractError::EntityLimitExceeded(XmlEntityLimit::Size, _))));
+	}
+	#[test]
+	fn escape_all() {
+		assert_eq!(escape("<a href=\"x\">Tom & Jerry's</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;".to_string());
+		assert_eq!(escape("plain"), "plain".to_string());
+	}
+	#[test]
+	fn escape_text_keeps_quotes() {
+		assert_eq!(escape_text("\"a\" < 'b' & ]]>"), "\"a\" &lt; 'b' &amp; ]]&gt;".to_string());
+	}
+	#[test]
+	fn escape_attribute_delimiter() {
+		assert_eq!(escape_attribute("say \"hi\" & 'bye'", QuoteKind::Double), "say &quot;hi&quot; &amp; 'bye'".to_string());
+		assert_eq!(escape_attribute("say \"hi\" & 'bye'", QuoteKind::Single), "say \"hi\" &amp; &apos;bye&apos;".to_string());
+	}
+	#[test]
+	fn escape_decode_round_trip() {
+		let text = "<\"'&'\">";
+		assert_eq!(decode(&escape(text), XmlPosition::start()).unwrap(), text.to_string());
+	}
+}
@@ -1,28 +1,190 @@
 use std::error;
 //use std::error::Error;
 use std::fmt;
+use std::io;
+use entity::XmlEntityLimit;
+use span::XmlPosition;
+use token::XmlToken;
 
 #[derive(Debug, Clone)]
 pub enum ExtractError{
-	EndTokenMissing(char),
-	BeginTokenMissing(char),
+	EndTokenMissing(char, XmlPosition),
+	BeginTokenMissing(char, XmlPosition),
+	UnexpectedToken(XmlToken<'static>, XmlPosition),
+	UnterminatedValue(char, XmlPosition),
+	InvalidNameChar(char, XmlPosition),
+	UnexpectedEnd(XmlPosition),
+	MismatchedTag(String, String, XmlPosition),
+	InvalidUtf8(u8, XmlPosition),
+	Io(io::ErrorKind, XmlPosition),
+	InvalidMarkup(char, XmlPosition),
+	InvalidComment(XmlPosition),
+	UnterminatedComment(XmlPosition),
+	UnterminatedCData(XmlPosition),
+	UnterminatedProcessingInstruction(XmlPosition),
+	InvalidDeclaration(String, XmlPosition),
+	MisplacedDeclaration(XmlPosition),
+	InvalidReference(String, XmlPosition),
+	UnknownEntity(String, XmlPosition),
+	InvalidChar(char, XmlPosition),
+	InvalidUtf16(u16, XmlPosition),
+	InvalidAscii(u8, XmlPosition),
+	UnsupportedEncoding(String, XmlPosition),
+	EncodingMismatch(String, String, XmlPosition),
+	InvalidDoctype(String, XmlPosition),
+	UnterminatedDoctype(XmlPosition),
+	MisplacedDoctype(XmlPosition),
+	MissingDoctype(XmlPosition),
+	RootMismatch(String, String, XmlPosition),
+	UndeclaredElement(String, XmlPosition),
+	InvalidContent(String, XmlPosition),
+	UndeclaredAttribute(String, String, XmlPosition),
+	MissingAttribute(String, String, XmlPosition),
+	InvalidAttributeValue(String, String, XmlPosition),
+	DuplicateId(String, XmlPosition),
+	UnknownIdRef(String, XmlPosition),
+	EntityLimitExceeded(XmlEntityLimit, XmlPosition),
+	RecursiveEntity(String, XmlPosition),
+	EntityMarkup(String, XmlPosition),
+	NestingTooDeep(usize, XmlPosition),
+	TooManyAttributes(usize, XmlPosition),
+	NameTooLong(usize, XmlPosition),
+	ValueTooLong(usize, XmlPosition),
+	TextTooLong(usize, XmlPosition),
+	TooManyTokens(usize, XmlPosition),
+	DocumentTooLarge(usize, XmlPosition),
+	UndeclaredPrefix(String, XmlPosition),
+	InvalidQName(String, XmlPosition),
+	InvalidNamespaceDeclaration(String, XmlPosition),
+	DuplicateAttribute(String, XmlPosition),
+	MultipleRoots(XmlPosition),
+	TextOutsideRoot(XmlPosition),
 }
 
 
 pub type ExtractResult<T> = Result<T, ExtractError>;
 
 
+impl ExtractError {
+	/// Position in the input where the error occurred.
+	pub fn position(&self) -> XmlPosition {
+		match *self {
+			ExtractError::EndTokenMissing(_, p) |
+			ExtractError::BeginTokenMissing(_, p) |
+			ExtractError::UnexpectedToken(_, p) |
+			ExtractError::UnterminatedValue(_, p) |
+			ExtractError::InvalidNameChar(_, p) |
+			ExtractError::UnexpectedEnd(p) |
+			ExtractError::MismatchedTag(_, _, p) |
+			ExtractError::InvalidUtf8(_, p) |
+			ExtractError::Io(_, p) |
+			ExtractError::InvalidMarkup(_, p) |
+			ExtractError::InvalidComment(p) |
+			ExtractError::UnterminatedComment(p) |
+			ExtractError::UnterminatedCData(p) |
+			ExtractError::UnterminatedProcessingInstruction(p) |
+			ExtractError::InvalidDeclaration(_, p) |
+			ExtractError::MisplacedDeclaration(p) |
+			ExtractError::InvalidReference(_, p) |
+			ExtractError::UnknownEntity(_, p) |
+			ExtractError::InvalidChar(_, p) |
+			ExtractError::InvalidUtf16(_, p) |
+			ExtractError::InvalidAscii(_, p) |
+			ExtractError::UnsupportedEncoding(_, p) |
+			ExtractError::EncodingMismatch(_, _, p) |
+			ExtractError::InvalidDoctype(_, p) |
+			ExtractError::UnterminatedDoctype(p) |
+			ExtractError::MisplacedDoctype(p) |
+			ExtractError::MissingDoctype(p) |
+			ExtractError::RootMismatch(_, _, p) |
+			ExtractError::UndeclaredElement(_, p) |
+			ExtractError::InvalidContent(_, p) |
+			ExtractError::UndeclaredAttribute(_, _, p) |
+			ExtractError::MissingAttribute(_, _, p) |
+			ExtractError::InvalidAttributeValue(_, _, p) |
+			ExtractError::DuplicateId(_, p) |
+			ExtractError::UnknownIdRef(_, p) |
+			ExtractError::EntityLimitExceeded(_, p) |
+			ExtractError::RecursiveEntity(_, p) |
+			ExtractError::EntityMarkup(_, p) |
+			ExtractError::NestingTooDeep(_, p) |
+			ExtractError::TooManyAttributes(_, p) |
+			ExtractError::NameTooLong(_, p) |
+			ExtractError::ValueTooLong(_, p) |
+			ExtractError::TextTooLong(_, p) |
+			ExtractError::TooManyTokens(_, p) |
+			ExtractError::DocumentTooLarge(_, p) |
+			ExtractError::UndeclaredPrefix(_, p) |
+			ExtractError::InvalidQName(_, p) |
+			ExtractError::InvalidNamespaceDeclaration(_, p) |
+			ExtractError::DuplicateAttribute(_, p) |
+			ExtractError::MultipleRoots(p) |
+			ExtractError::TextOutsideRoot(p) => p,
+		}
+	}
+}
+
+
 // This is important for other errors to wrap this one.
 #[warn(unreachable_patterns)]
 impl error::Error for ExtractError {
 	fn description(&self) -> &str {
 		match self {
-			ExtractError::EndTokenMissing(_c) => "Missing end token",
-			ExtractError::BeginTokenMissing(_c) => "Missing begin token",
+			ExtractError::EndTokenMissing(_c, _p) => "Missing end token",
+			ExtractError::BeginTokenMissing(_c, _p) => "Missing begin token",
+			ExtractError::UnexpectedToken(_t, _p) => "Unexpected token",
+			ExtractError::UnterminatedValue(_c, _p) => "Unterminated attribute value",
+			ExtractError::InvalidNameChar(_c, _p) => "Invalid character in name",
+			ExtractError::UnexpectedEnd(_p) => "Unexpected end of input",
+			ExtractError::MismatchedTag(_e, _f, _p) => "Mismatched end tag",
+			ExtractError::InvalidUtf8(_b, _p) => "Invalid UTF-8 sequence",
+			ExtractError::Io(_k, _p) => "Read error",
+			ExtractError::InvalidMarkup(_c, _p) => "Invalid markup",
+			ExtractError::InvalidComment(_p) => "Invalid comment",
+			ExtractError::UnterminatedComment(_p) => "Unterminated comment",
+			ExtractError::UnterminatedCData(_p) => "Unterminated CDATA section",
+			ExtractError::UnterminatedProcessingInstruction(_p) => "Unterminated processing instruction",
+			ExtractError::InvalidDeclaration(_t, _p) => "Invalid XML declaration",
+			ExtractError::MisplacedDeclaration(_p) => "XML declaration not at the start of the document",
+			ExtractError::InvalidReference(_r, _p) => "Invalid reference",
+			ExtractError::UnknownEntity(_n, _p) => "Unknown entity",
+			ExtractError::InvalidChar(_c, _p) => "Character not allowed in XML",
+			ExtractError::InvalidUtf16(_u, _p) => "Invalid UTF-16 sequence",
+			ExtractError::InvalidAscii(_b, _p) => "Byte outside of ASCII",
+			ExtractError::UnsupportedEncoding(_e, _p) => "Unsupported encoding",
+			ExtractError::EncodingMismatch(_d, _e, _p) => "Declared encoding does not match the input",
+			ExtractError::InvalidDoctype(_t, _p) => "Invalid document type declaration",
+			ExtractError::UnterminatedDoctype(_p) => "Unterminated document type declaration",
+			ExtractError::MisplacedDoctype(_p) => "Document type declaration not before the root element",
+			ExtractError::MissingDoctype(_p) => "Document type declaration missing",
+			ExtractError::RootMismatch(_d, _e, _p) => "Root element does not match the document type",
+			ExtractError::UndeclaredElement(_e, _p) => "Undeclared element",
+			ExtractError::InvalidContent(_e, _p) => "Element content does not match its declaration",
+			ExtractError::UndeclaredAttribute(_e, _a, _p) => "Undeclared attribute",
+			ExtractError::MissingAttribute(_e, _a, _p) => "Required attribute missing",
+			ExtractError::InvalidAttributeValue(_a, _v, _p) => "Attribute value does not match its declaration",
+			ExtractError::DuplicateId(_i, _p) => "Duplicate ID",
+			ExtractError::UnknownIdRef(_i, _p) => "Reference to an unknown ID",
+			ExtractError::EntityLimitExceeded(_l, _p) => "Entity expansion limit exceeded",
+			ExtractError::RecursiveEntity(_n, _p) => "Recursive entity reference",
+			ExtractError::EntityMarkup(_n, _p) => "Entity with markup in its replacement text",
+			ExtractError::NestingTooDeep(_l, _p) => "Elements nested too deeply",
+			ExtractError::TooManyAttributes(_l, _p) => "Too many attributes",
+			ExtractError::NameTooLong(_l, _p) => "Name too long",
+			ExtractError::ValueTooLong(_l, _p) => "Attribute value too long",
+			ExtractError::TextTooLong(_l, _p) => "Text too long",
+			ExtractError::TooManyTokens(_l, _p) => "Too many tokens",
+			ExtractError::DocumentTooLarge(_l, _p) => "Document too large",
+			ExtractError::UndeclaredPrefix(_n, _p) => "Undeclared namespace prefix",
+			ExtractError::InvalidQName(_n, _p) => "Invalid qualified name",
+			ExtractError::InvalidNamespaceDeclaration(_n, _p) => "Invalid namespace declaration",
+			ExtractError::DuplicateAttribute(_n, _p) => "Duplicate attribute",
+			ExtractError::MultipleRoots(_p) => "More than one root element",
+			ExtractError::TextOutsideRoot(_p) => "Text outside the root element",
 		}
 	}
 
-	fn cause(&self) -> Option<&error::Error> {
+	fn cause(&self) -> Option<&dyn error::Error> {
 		// Generic error, underlying cause isn't tracked.
 		None
 	}
@@ -32,8 +194,57 @@ impl error::Error for ExtractError {
 impl fmt::Display for ExtractError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			ExtractError::EndTokenMissing(c) => write!(f, "Missing end token {}", c),
-			ExtractError::BeginTokenMissing(c) => write!(f, "Missing begin token {}", c),
+			ExtractError::EndTokenMissing(c, p) => write!(f, "Missing end token {} at {}", c, p),
+			ExtractError::BeginTokenMissing(c, p) => write!(f, "Missing begin token {} at {}", c, p),
+			ExtractError::UnexpectedToken(t, p) => write!(f, "Unexpected token {} at {}", t, p),
+			ExtractError::UnterminatedValue(c, p) => write!(f, "Unterminated attribute value starting with {} at {}", c, p),
+			ExtractError::InvalidNameChar(c, p) => write!(f, "Invalid character in name {} at {}", c, p),
+			ExtractError::UnexpectedEnd(p) => write!(f, "Unexpected end of input at {}", p),
+			ExtractError::MismatchedTag(e, t, p) => write!(f, "Mismatched end tag {} for {} at {}", t, e, p),
+			ExtractError::InvalidUtf8(b, p) => write!(f, "Invalid UTF-8 sequence starting with 0x{:02X} at {}", b, p),
+			ExtractError::Io(k, p) => write!(f, "Read error {:?} at {}", k, p),
+			ExtractError::InvalidMarkup(c, p) => write!(f, "Invalid markup character {} at {}", c, p),
+			ExtractError::InvalidComment(p) => write!(f, "Invalid -- in comment starting at {}", p),
+			ExtractError::UnterminatedComment(p) => write!(f, "Unterminated comment starting at {}", p),
+			ExtractError::UnterminatedCData(p) => write!(f, "Unterminated CDATA section starting at {}", p),
+			ExtractError::UnterminatedProcessingInstruction(p) => write!(f, "Unterminated processing instruction starting at {}", p),
+			ExtractError::InvalidDeclaration(t, p) => write!(f, "Invalid XML declaration {} at {}", t, p),
+			ExtractError::MisplacedDeclaration(p) => write!(f, "XML declaration not at the start of the document at {}", p),
+			ExtractError::InvalidReference(r, p) => write!(f, "Invalid reference &{} at {}", r, p),
+			ExtractError::UnknownEntity(n, p) => write!(f, "Unknown entity &{}; at {}", n, p),
+			ExtractError::InvalidChar(c, p) => write!(f, "Character U+{:04X} not allowed in XML at {}", *c as u32, p),
+			ExtractError::InvalidUtf16(u, p) => write!(f, "Unpaired UTF-16 surrogate 0x{:04X} at {}", u, p),
+			ExtractError::InvalidAscii(b, p) => write!(f, "Byte 0x{:02X} outside of ASCII at {}", b, p),
+			ExtractError::UnsupportedEncoding(e, p) => write!(f, "Unsupported encoding {} at {}", e, p),
+			ExtractError::EncodingMismatch(d, e, p) => write!(f, "Declared encoding {} does not match detected {} at {}", d, e, p),
+			ExtractError::InvalidDoctype(t, p) => write!(f, "Invalid document type declaration {} at {}", t, p),
+			ExtractError::UnterminatedDoctype(p) => write!(f, "Unterminated document type declaration starting at {}", p),
+			ExtractError::MisplacedDoctype(p) => write!(f, "Document type declaration not before the root element at {}", p),
+			ExtractError::MissingDoctype(p) => write!(f, "Document type declaration missing for the element at {}", p),
+			ExtractError::RootMismatch(d, e, p) => write!(f, "Root element {} does not match the document type {} at {}", e, d, p),
+			ExtractError::UndeclaredElement(e, p) => write!(f, "Undeclared element {} at {}", e, p),
+			ExtractError::InvalidContent(e, p) => write!(f, "Content of element {} does not match its declaration at {}", e, p),
+			ExtractError::UndeclaredAttribute(e, a, p) => write!(f, "Undeclared attribute {} of element {} at {}", a, e, p),
+			ExtractError::MissingAttribute(e, a, p) => write!(f, "Required attribute {} of element {} missing at {}", a, e, p),
+			ExtractError::InvalidAttributeValue(a, v, p) => write!(f, "Value {} of attribute {} does not match its declaration at {}", v, a, p),
+			ExtractError::DuplicateId(i, p) => write!(f, "Duplicate ID {} at {}", i, p),
+			ExtractError::UnknownIdRef(i, p) => write!(f, "Reference to unknown ID {} at {}", i, p),
+			ExtractError::EntityLimitExceeded(l, p) => write!(f, "Entity expansion exceeds the {} limit at {}", l, p),
+			ExtractError::RecursiveEntity(n, p) => write!(f, "Entity &{}; references itself at {}", n, p),
+			ExtractError::EntityMarkup(n, p) => write!(f, "Entity &{}; contains markup, which is not supported, at {}", n, p),
+			ExtractError::NestingTooDeep(l, p) => write!(f, "Elements nested deeper than {} at {}", l, p),
+			ExtractError::TooManyAttributes(l, p) => write!(f, "More than {} attributes at {}", l, p),
+			ExtractError::NameTooLong(l, p) => write!(f, "Name longer than {} bytes at {}", l, p),
+			ExtractError::ValueTooLong(l, p) => write!(f, "Attribute value longer than {} bytes at {}", l, p),
+			ExtractError::TextTooLong(l, p) => write!(f, "Text longer than {} bytes at {}", l, p),
+			ExtractError::TooManyTokens(l, p) => write!(f, "More than {} tokens at {}", l, p),
+			ExtractError::DocumentTooLarge(l, p) => write!(f, "Document larger than {} bytes at {}", l, p),
+			ExtractError::UndeclaredPrefix(n, p) => write!(f, "Undeclared namespace prefix {} at {}", n, p),
+			ExtractError::InvalidQName(n, p) => write!(f, "Invalid qualified name {} at {}", n, p),
+			ExtractError::InvalidNamespaceDeclaration(n, p) => write!(f, "Invalid namespace declaration {} at {}", n, p),
+			ExtractError::DuplicateAttribute(n, p) => write!(f, "Duplicate attribute {} at {}", n, p),
+			ExtractError::MultipleRoots(p) => write!(f, "Second root element at {}", p),
+			ExtractError::TextOutsideRoot(p) => write!(f, "Text outside the root element at {}", p),
 		}
 	}
 }
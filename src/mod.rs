@@ -4,3 +4,15 @@ pub mod tokenizer;
 pub mod errors;
 pub mod tokenizer_iterator;
 pub mod tree;
+pub mod arena;
+pub mod parser;
+pub mod span;
+pub mod stream;
+pub mod declaration;
+pub mod doctype;
+pub mod entity;
+pub mod encoding;
+pub mod options;
+pub mod validation;
+pub mod namespace;
+pub mod writer;
@@ -0,0 +1,193 @@
+use std::fmt;
+use attribute::XmlAttribute;
+use errors::ExtractError;
+use errors::ExtractResult;
+use span::XmlPosition;
+
+
+/// Namespace bound to the `xml` prefix without a declaration.
+pub const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+/// Namespace of the `xmlns` attributes, which can not be bound to any prefix.
+pub const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+
+/// Name of an element or attribute split at its prefix, with the namespace
+/// the prefix is bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XmlQName {
+	pub prefix: Option<String>,
+	pub local_name: String,
+	pub namespace: Option<String>,
+}
+
+
+impl XmlQName {
+	pub fn new(name: &str, namespace: Option<String>) -> XmlQName {
+		let (prefix, local_name) = XmlQName::split(name);
+		XmlQName {
+			prefix: prefix.map(String::from),
+			local_name: local_name.to_string(),
+			namespace,
+		}
+	}
+	/// Prefix and local name of `prefix:local`.
+	pub fn split(name: &str) -> (Option<&str>, &str) {
+		match name.find(':') {
+			Some(index) => (Some(&name[..index]), &name[index + 1..]),
+			None => (None, name),
+		}
+	}
+	/// Namespace and local name, which identify the name whatever the prefix is.
+	pub fn expanded_name(&self) -> (Option<&str>, &str) {
+		(self.namespace.as_deref(), &self.local_name)
+	}
+}
+
+
+impl fmt::Display for XmlQName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.prefix {
+			Some(ref prefix) => write!(f, "{}:{}", prefix, self.local_name),
+			None => write!(f, "{}", self.local_name),
+		}
+	}
+}
+
+
+/// Namespace declarations in scope, one level for every open element.
+#[derive(Debug, Clone, Default)]
+pub struct XmlNamespaces {
+	scopes: Vec<Vec<(String, String)>>, // prefix, empty for the default namespace, and namespace
+}
+
+
+impl XmlNamespaces {
+	pub fn new() -> XmlNamespaces {
+		XmlNamespaces::default()
+	}
+
+	/// Opens the scope of an element with the declarations among its attributes.
+	pub fn push(&mut self, attributes: &[XmlAttribute], position: XmlPosition) -> ExtractResult<()> {
+		let mut declarations = vec![];
+		for attribute in attributes {
+			let prefix = match XmlQName::split(&attribute.name) {
+				(None, "xmlns") => "",
+				(Some("xmlns"), prefix) => prefix,
+				_ => continue,
+			};
+			let namespace = attribute.value.as_str();
+			let invalid = match prefix {
+				"xml" => namespace != XML_NAMESPACE,
+				"xmlns" => true,
+				// only the default namespace can be undeclared
+				_ => namespace == XML_NAMESPACE || namespace == XMLNS_NAMESPACE || (namespace.is_empty() && !prefix.is_empty()),
+			};
+			if invalid {
+				return Err(ExtractError::InvalidNamespaceDeclaration(attribute.name.clone(), position));
+			}
+			declarations.push((prefix.to_string(), namespace.to_string()));
+		}
+		self.scopes.push(declarations);
+		Ok(())
+	}
+
+	/// Closes the scope of the innermost element.
+	pub fn pop(&mut self) {
+		self.scopes.pop();
+	}
+
+	/// Namespace bound to the prefix, an empty prefix stands for the default namespace.
+	pub fn lookup(&self, prefix: &str) -> Option<&str> {
+		match prefix {
+			"xml" => return Some(XML_NAMESPACE),
+			"xmlns" => return Some(XMLNS_NAMESPACE),
+			_ => (),
+		}
+		self.scopes.iter().rev()
+			.flat_map(|declarations| declarations.iter().rev())
+			.find(|declaration| declaration.0 == prefix)
+			.map(|declaration| declaration.1.as_str())
+			.filter(|namespace| !namespace.is_empty())
+	}
+
+	/// Qualified name of an element, unprefixed names are in the default namespace.
+	pub fn resolve_element(&self, name: &str, position: XmlPosition) -> ExtractResult<XmlQName> {
+		self.resolve(name, true, position)
+	}
+
+	/// Qualified name of an attribute, unprefixed names are in no namespace.
+	pub fn resolve_attribute(&self, name: &str, position: XmlPosition) -> ExtractResult<XmlQName> {
+		if name == "xmlns" {
+			return Ok(XmlQName::new(name, Some(XMLNS_NAMESPACE.to_string())));
+		}
+		self.resolve(name, false, position)
+	}
+
+	fn resolve(&self, name: &str, default: bool, position: XmlPosition) -> ExtractResult<XmlQName> {
+		let namespace = match XmlQName::split(name) {
+			(Some(prefix), local) => {
+				if prefix.is_empty() || local.is_empty() || local.contains(':') {
+					return Err(ExtractError::InvalidQName(name.to_string(), position));
+				}
+				let namespace = self.lookup(prefix).ok_or_else(|| ExtractError::UndeclaredPrefix(prefix.to_string(), position))?;
+				Some(namespace.to_string())
+			},
+			(None, _) if default => self.lookup("").map(String::from),
+			(None, _) => None,
+		};
+		Ok(XmlQName::new(name, namespace))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use attribute::XmlAttribute;
+	use errors::ExtractError;
+	use span::XmlPosition;
+	use super::*;
+
+	fn declarations(attributes: &[(&str, &str)]) -> Vec<XmlAttribute> {
+		attributes.iter().map(|&(name, value)| XmlAttribute::new(name.to_string(), value.to_string()).unwrap()).collect()
+	}
+
+	#[test]
+	fn split_names() {
+		assert_eq!(XmlQName::split("soap:Envelope"), (Some("soap"), "Envelope"));
+		assert_eq!(XmlQName::split("Envelope"), (None, "Envelope"));
+		let name = XmlQName::new("s:Envelope", Some("urn:soap".to_string()));
+		assert_eq!(name.expanded_name(), (Some("urn:soap"), "Envelope"));
+		assert_eq!(name.to_string(), "s:Envelope".to_string());
+	}
+	#[test]
+	fn resolve_in_scope() {
+		let position = XmlPosition::start();
+		let mut namespaces = XmlNamespaces::new();
+		namespaces.push(&declarations(&[("xmlns", "urn:a"), ("xmlns:b", "urn:b")]), position).unwrap();
+		assert_eq!(namespaces.resolve_element("e", position).unwrap().namespace, Some("urn:a".to_string()));
+		assert_eq!(namespaces.resolve_attribute("e", position).unwrap().namespace, None);
+		assert_eq!(namespaces.resolve_attribute("b:e", position).unwrap().namespace, Some("urn:b".to_string()));
+		assert_eq!(namespaces.resolve_attribute("xml:lang", position).unwrap().namespace, Some(XML_NAMESPACE.to_string()));
+		namespaces.push(&declarations(&[("xmlns", ""), ("xmlns:b", "urn:c")]), position).unwrap();
+		assert_eq!(namespaces.resolve_element("e", position).unwrap().namespace, None);
+		assert_eq!(namespaces.resolve_element("b:e", position).unwrap().namespace, Some("urn:c".to_string()));
+		namespaces.pop();
+		assert_eq!(namespaces.resolve_element("b:e", position).unwrap().namespace, Some("urn:b".to_string()));
+		namespaces.pop();
+		assert!(matches!(namespaces.resolve_element("b:e", position), Err(ExtractError::UndeclaredPrefix(ref prefix, _)) if prefix == "b"));
+	}
+	#[test]
+	fn invalid_names_and_declarations() {
+		let position = XmlPosition::start();
+		let namespaces = XmlNamespaces::new();
+		assert!(matches!(namespaces.resolve_element(":e", position), Err(ExtractError::InvalidQName(_, _))));
+		assert!(matches!(namespaces.resolve_element("a:b:c", position), Err(ExtractError::InvalidQName(_, _))));
+		let invalid = |name: &str, value: &str| matches!(XmlNamespaces::new().push(&declarations(&[(name, value)]), position), Err(ExtractError::InvalidNamespaceDeclaration(_, _)));
+		assert!(invalid("xmlns:p", ""));
+		assert!(invalid("xmlns:xml", "urn:a"));
+		assert!(invalid("xmlns:xmlns", XMLNS_NAMESPACE));
+		assert!(invalid("xmlns:p", XML_NAMESPACE));
+		assert!(invalid("xmlns", XMLNS_NAMESPACE));
+		assert!(!invalid("xmlns:xml", XML_NAMESPACE));
+	}
+}
@@ -0,0 +1,214 @@
+/// How line breaks in the input are passed on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum XmlLineEndings {
+	/// Keep `\r\n` and `\r` as they are, so the input can be reproduced exactly.
+	#[default]
+	Preserve,
+	/// Turn `\r\n` and a lone `\r` into `\n`, as required by section 2.11 of XML 1.0.
+	Normalize,
+}
+
+
+/// Bounds on the expansion of entities declared in the DTD, which protect
+/// against documents that expand to far more than their own size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct XmlEntityLimits {
+	/// Entities nested in the replacement text of other entities.
+	pub max_depth: usize,
+	/// Bytes of replacement text over the whole document.
+	pub max_size: usize,
+	/// Expanded references over the whole document.
+	pub max_expansions: usize,
+}
+
+
+impl Default for XmlEntityLimits {
+	fn default() -> XmlEntityLimits {
+		XmlEntityLimits {
+			max_depth: 16,
+			max_size: 1 << 20,
+			max_expansions: 10_000,
+		}
+	}
+}
+
+
+/// Bounds on the size of the input, checked while tokenizing so that parsers
+/// reading from a tokenizer are bound by them as well. The tree builder checks
+/// depth and attributes again for tokens from other sources. Lengths are in
+/// bytes. By default depth, attributes and document size are bounded, the
+/// other limits are off; `unlimited` turns all of them off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct XmlLimits {
+	/// Elements open at the same time.
+	pub max_depth: usize,
+	pub max_attributes: usize,
+	pub max_name_length: usize,
+	pub max_value_length: usize,
+	/// Character data, comments, CDATA sections, processing instructions and
+	/// whitespace inside tags.
+	pub max_text_length: usize,
+	pub max_tokens: usize,
+	pub max_document_size: usize,
+}
+
+
+impl Default for XmlLimits {
+	fn default() -> XmlLimits {
+		XmlLimits {
+			max_depth: 256,
+			max_attributes: 1024,
+			max_document_size: 1 << 30,
+			..XmlLimits::unlimited()
+		}
+	}
+}
+
+
+impl XmlLimits {
+	/// Limits that are all off, for trusted input only.
+	pub fn unlimited() -> XmlLimits {
+		XmlLimits {
+			max_depth: usize::MAX,
+			max_attributes: usize::MAX,
+			max_name_length: usize::MAX,
+			max_value_length: usize::MAX,
+			max_text_length: usize::MAX,
+			max_tokens: usize::MAX,
+			max_document_size: usize::MAX,
+		}
+	}
+}
+
+
+/// Settings for tokenizing and parsing a document.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XmlOptions {
+	pub line_endings: XmlLineEndings,
+	pub entity_limits: XmlEntityLimits,
+	pub limits: XmlLimits,
+	/// Resolve the prefixes of element and attribute names while parsing,
+	/// names that are not valid qualified names are rejected then.
+	pub namespace_aware: bool,
+	/// Check the parsed document against the DTD of its DOCTYPE.
+	pub validate: bool,
+}
+
+
+impl XmlOptions {
+	pub fn new() -> XmlOptions {
+		XmlOptions::default()
+	}
+	pub fn with_line_endings(mut self, line_endings: XmlLineEndings) -> XmlOptions {
+		self.line_endings = line_endings;
+		self
+	}
+	pub fn with_entity_limits(mut self, entity_limits: XmlEntityLimits) -> XmlOptions {
+		self.entity_limits = entity_limits;
+		self
+	}
+	pub fn with_limits(mut self, limits: XmlLimits) -> XmlOptions {
+		self.limits = limits;
+		self
+	}
+	pub fn with_namespace_aware(mut self, namespace_aware: bool) -> XmlOptions {
+		self.namespace_aware = namespace_aware;
+		self
+	}
+	pub fn with_validate(mut self, validate: bool) -> XmlOptions {
+		self.validate = validate;
+		self
+	}
+}
+
+
+/// When start tags are broken into one line per attribute.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum XmlAttributeWrapping {
+	#[default]
+	Never,
+	/// Only tags that would not fit into the line width.
+	OnOverflow,
+	/// Every tag with more than one attribute.
+	Always,
+}
+
+
+/// Whether the XML declaration is written before the document.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum XmlDeclarationOutput {
+	/// Write the declaration the document was parsed with, if any.
+	#[default]
+	Keep,
+	/// Write the declaration of the document or a version 1.0 one.
+	Always,
+	Omit,
+}
+
+
+/// Settings for writing a tree. The default writes the tree as it was parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlWriteOptions {
+	/// Indentation of one level, `None` adds no line breaks between nodes.
+	pub indent: Option<String>,
+	/// Characters per line, only used to decide on attribute wrapping.
+	pub line_width: usize,
+	pub attribute_wrapping: XmlAttributeWrapping,
+	/// Write elements without content as `<name/>` rather than `<name></name>`.
+	pub self_closing: bool,
+	/// Keep whitespace-only text, otherwise it is dropped from elements
+	/// without other text, the indentation replaces it there.
+	pub preserve_whitespace: bool,
+	pub declaration: XmlDeclarationOutput,
+}
+
+
+impl Default for XmlWriteOptions {
+	fn default() -> XmlWriteOptions {
+		XmlWriteOptions {
+			indent: None,
+			line_width: 80,
+			attribute_wrapping: XmlAttributeWrapping::default(),
+			self_closing: true,
+			preserve_whitespace: true,
+			declaration: XmlDeclarationOutput::default(),
+		}
+	}
+}
+
+
+impl XmlWriteOptions {
+	pub fn new() -> XmlWriteOptions {
+		XmlWriteOptions::default()
+	}
+	/// Reformats the tree, indenting every level with the given string.
+	pub fn pretty(indent: &str) -> XmlWriteOptions {
+		XmlWriteOptions::default()
+			.with_indent(Some(indent.to_string()))
+			.with_preserve_whitespace(false)
+	}
+	pub fn with_indent(mut self, indent: Option<String>) -> XmlWriteOptions {
+		self.indent = indent;
+		self
+	}
+	pub fn with_line_width(mut self, line_width: usize) -> XmlWriteOptions {
+		self.line_width = line_width;
+		self
+	}
+	pub fn with_attribute_wrapping(mut self, attribute_wrapping: XmlAttributeWrapping) -> XmlWriteOptions {
+		self.attribute_wrapping = attribute_wrapping;
+		self
+	}
+	pub fn with_self_closing(mut self, self_closing: bool) -> XmlWriteOptions {
+		self.self_closing = self_closing;
+		self
+	}
+	pub fn with_preserve_whitespace(mut self, preserve_whitespace: bool) -> XmlWriteOptions {
+		self.preserve_whitespace = preserve_whitespace;
+		self
+	}
+	pub fn with_declaration(mut self, declaration: XmlDeclarationOutput) -> XmlWriteOptions {
+		self.declaration = declaration;
+		self
+	}
+}
@@ -0,0 +1,734 @@
+use std::io::BufRead;
+use std::iter::Peekable;
+use arena::Arena;
+use attribute::XmlAttribute;
+use declaration::XmlDeclaration;
+use doctype::XmlDoctype;
+use errors::ExtractError;
+use errors::ExtractResult;
+use namespace::XmlNamespaces;
+use namespace::XmlQName;
+use options::XmlLimits;
+use options::XmlOptions;
+use span::XmlPosition;
+use span::XmlSpanned;
+use stream::XmlStreamTokenizer;
+use token::XmlToken;
+use token::XmlWhitespace;
+use tokenizer::XmlTokenVec;
+use tokenizer_iterator::XmlTokenizer;
+use tree::Hierarchical;
+use tree::Node;
+use validation;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlElement {
+	Document { declaration: Option<XmlDeclaration>, doctype: Option<XmlDoctype> }, // root of every parsed tree
+	Element { name: String, attributes: Vec<XmlAttribute>, namespace: Option<String> }, // <name attribute="value">
+	Text(String), // character data
+	Whitespace(XmlWhitespace), // character data consisting of whitespace only
+	Comment(String), // <!-- comment -->
+	CData(String), // <![CDATA[ text ]]>
+	ProcessingInstruction { target: String, data: String }, // <?target data?>
+}
+
+pub type XmlNode = Node<XmlElement>;
+/// Parsed tree converted with `XmlDocument::from`, to navigate to parents and siblings.
+pub type XmlDocument = Arena<XmlElement>;
+
+type XmlTokenResult<'a> = ExtractResult<XmlSpanned<XmlToken<'a>>>;
+
+
+impl XmlElement {
+	pub fn new_document(declaration: Option<XmlDeclaration>) -> XmlElement {
+		XmlElement::Document { declaration, doctype: None }
+	}
+	pub fn new_element(name: String, attributes: Vec<XmlAttribute>) -> XmlElement {
+		XmlElement::Element { name, attributes, namespace: None }
+	}
+	pub fn new_text(value: String) -> XmlElement {
+		XmlElement::Text(value)
+	}
+	pub fn name(&self) -> Option<&str> {
+		match self {
+			XmlElement::Element { name, .. } => Some(name),
+			_ => None,
+		}
+	}
+	/// Name of an element split at its prefix, with its namespace.
+	pub fn qname(&self) -> Option<XmlQName> {
+		match self {
+			XmlElement::Element { name, namespace, .. } => Some(XmlQName::new(name, namespace.clone())),
+			_ => None,
+		}
+	}
+	pub fn declaration(&self) -> Option<&XmlDeclaration> {
+		match self {
+			XmlElement::Document { declaration, .. } => declaration.as_ref(),
+			_ => None,
+		}
+	}
+	pub fn doctype(&self) -> Option<&XmlDoctype> {
+		match self {
+			XmlElement::Document { doctype, .. } => doctype.as_ref(),
+			_ => None,
+		}
+	}
+	pub fn attributes(&self) -> &[XmlAttribute] {
+		match self {
+			XmlElement::Element { attributes, .. } => attributes,
+			_ => &[],
+		}
+	}
+	pub fn attribute(&self, name: &str) -> Option<&str> {
+		self.attributes().iter()
+			.find(|attribute| attribute.name == name)
+			.map(|attribute| attribute.value.as_str())
+	}
+}
+
+
+pub trait XmlParser {
+	/// Parses the document with the default options, which also bound tokens
+	/// from a tokenizer that was given larger limits.
+	fn parse(self) -> ExtractResult<XmlNode>;
+	/// Parses the document with the limits, namespace processing and
+	/// validation set in the options.
+	fn parse_with(self, options: &XmlOptions) -> ExtractResult<XmlNode>;
+}
+
+impl<'a> XmlParser for XmlTokenVec<'a> {
+	fn parse(self) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(&XmlOptions::default()).build(self.into_iter().map(Ok))
+	}
+	fn parse_with(self, options: &XmlOptions) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(options).build(self.into_iter().map(Ok))
+	}
+}
+
+impl<'a, I: Iterator<Item = char>> XmlParser for XmlTokenizer<'a, I> {
+	fn parse(self) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(&XmlOptions::default()).build(self)
+	}
+	fn parse_with(self, options: &XmlOptions) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(options).build(self)
+	}
+}
+
+impl<R: BufRead> XmlParser for XmlStreamTokenizer<R> {
+	fn parse(self) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(&XmlOptions::default()).build(self)
+	}
+	fn parse_with(self, options: &XmlOptions) -> ExtractResult<XmlNode> {
+		XmlTreeBuilder::new(options).build(self)
+	}
+}
+
+
+struct XmlTokenCursor<I: Iterator> {
+	iter: Peekable<I>,
+	position: XmlPosition, // start of the last returned token
+	end: XmlPosition, // end of the last returned token
+}
+
+/// Tokens are handed out owned, the tree keeps their text anyway.
+impl<'a, I: Iterator<Item = XmlTokenResult<'a>>> XmlTokenCursor<I> {
+	fn new(tokens: I) -> Self {
+		XmlTokenCursor {
+			iter: tokens.peekable(),
+			position: XmlPosition::start(),
+			end: XmlPosition::start(),
+		}
+	}
+	fn next(&mut self) -> ExtractResult<Option<XmlToken<'static>>> {
+		match self.iter.next() {
+			Some(Ok(spanned)) => {
+				self.position = spanned.span.start;
+				self.end = spanned.span.end;
+				Ok(Some(spanned.token.into_owned()))
+			},
+			Some(Err(error)) => Err(error),
+			None => Ok(None),
+		}
+	}
+	/// Next token, `None` at the end of the input or when the next token is an error.
+	fn peek(&mut self) -> Option<&XmlToken<'a>> {
+		match self.iter.peek() {
+			Some(Ok(spanned)) => Some(&spanned.token),
+			_ => None,
+		}
+	}
+}
+
+
+struct XmlTreeBuilder {
+	root: XmlNode,
+	open: Vec<XmlNode>, // open elements with the children read so far, added to their parent when closed
+	root_element: bool, // whether the root element has started
+	content: Vec<XmlToken<'static>>, // character data not yet added to the tree
+	content_position: XmlPosition, // start of the character data not yet added
+	positions: Option<Vec<XmlPosition>>, // start of every element in document order when validating
+	namespaces: Option<XmlNamespaces>, // declarations in scope when namespace aware
+	limits: XmlLimits,
+}
+
+impl XmlTreeBuilder {
+	fn new(options: &XmlOptions) -> Self {
+		XmlTreeBuilder {
+			root: Node::new(XmlElement::new_document(None)),
+			open: vec![],
+			root_element: false,
+			content: vec![],
+			content_position: XmlPosition::start(),
+			positions: if options.validate { Some(vec![]) } else { None },
+			namespaces: if options.namespace_aware { Some(XmlNamespaces::new()) } else { None },
+			limits: options.limits,
+		}
+	}
+
+	fn build<'a, I: Iterator<Item = XmlTokenResult<'a>>>(mut self, tokens: I) -> ExtractResult<XmlNode> {
+		let mut iter = XmlTokenCursor::new(tokens);
+		while let Some(token) = iter.next()? {
+			match token {
+				XmlToken::Begin => {
+					self.flush_content()?;
+					if iter.peek() == Some(&XmlToken::Close) {
+						iter.next()?;
+						self.parse_end_tag(&mut iter)?;
+					}
+					else {
+						self.parse_start_tag(&mut iter)?;
+					}
+				},
+				XmlToken::Comment(text) => {
+					self.flush_content()?;
+					self.current().add_child(XmlElement::Comment(text.into_owned()));
+				},
+				XmlToken::CData(text) => {
+					self.flush_content()?;
+					if self.open.is_empty() {
+						return Err(ExtractError::TextOutsideRoot(iter.position));
+					}
+					self.current().add_child(XmlElement::CData(text.into_owned()));
+				},
+				XmlToken::Declaration(declaration) => {
+					if self.root.get_count() > 0 || !self.content.is_empty() || self.root.get_data().declaration().is_some() || self.root.get_data().doctype().is_some() {
+						return Err(ExtractError::MisplacedDeclaration(iter.position));
+					}
+					self.root = Node::new(XmlElement::new_document(Some(declaration)));
+				},
+				XmlToken::Doctype(doctype) => {
+					self.flush_content()?;
+					// only one, and only before the root element
+					match self.root.get_data_mut() {
+						XmlElement::Document { doctype: current, .. } if current.is_none() && !self.root_element => *current = Some(doctype),
+						_ => return Err(ExtractError::MisplacedDoctype(iter.position)),
+					}
+				},
+				XmlToken::ProcessingInstruction(target, data) => {
+					self.flush_content()?;
+					self.current().add_child(XmlElement::ProcessingInstruction { target: target.into_owned(), data: data.into_owned() });
+				},
+				other => {
+					if self.content.is_empty() {
+						self.content_position = iter.position;
+					}
+					self.content.push(other);
+				},
+			}
+		}
+		self.flush_content()?;
+		if !self.open.is_empty() {
+			return Err(ExtractError::UnexpectedEnd(iter.end));
+		}
+		if let Some(ref positions) = self.positions {
+			validation::validate(&self.root, positions)?;
+		}
+		Ok(self.root)
+	}
+
+	fn current(&mut self) -> &mut XmlNode {
+		match self.open.last_mut() {
+			Some(node) => node,
+			None => &mut self.root,
+		}
+	}
+
+	/// Adds the character data read since the last markup, only whitespace
+	/// may appear outside the root element.
+	fn flush_content(&mut self) -> ExtractResult<()> {
+		if self.content.is_empty() {
+			return Ok(());
+		}
+		let content: Vec<XmlToken> = self.content.drain(..).collect();
+		let whitespace = content.iter().all(|token| matches!(token, XmlToken::Whitespace(_)));
+		if !whitespace && self.open.is_empty() {
+			return Err(ExtractError::TextOutsideRoot(self.content_position));
+		}
+		let text: String = content.iter().map(|token| match token {
+			XmlToken::Text(s) => s.to_string(),
+			other => other.to_string(),
+		}).collect();
+		let node = self.current();
+		if whitespace {
+			node.add_child(XmlElement::Whitespace(XmlWhitespace::new(text).unwrap()));
+		}
+		else {
+			node.add_child(XmlElement::new_text(text));
+		}
+		Ok(())
+	}
+
+	fn parse_start_tag<'a, I: Iterator<Item = XmlTokenResult<'a>>>(&mut self, iter: &mut XmlTokenCursor<I>) -> ExtractResult<()> {
+		let start = iter.position;
+		if let Some(ref mut positions) = self.positions {
+			positions.push(start);
+		}
+		if self.open.len() >= self.limits.max_depth {
+			return Err(ExtractError::NestingTooDeep(self.limits.max_depth, start));
+		}
+		if self.open.is_empty() {
+			if self.root_element {
+				return Err(ExtractError::MultipleRoots(start));
+			}
+			self.root_element = true;
+		}
+		let (name, position) = Self::expect_name(iter)?;
+		let mut attributes = vec![];
+		loop {
+			let separated = Self::skip_whitespace(iter)?;
+			match iter.next()? {
+				Some(XmlToken::Name(attribute)) => {
+					let attribute_position = iter.position;
+					if !separated {
+						return Err(ExtractError::UnexpectedToken(XmlToken::Name(attribute), attribute_position));
+					}
+					if attributes.len() >= self.limits.max_attributes {
+						return Err(ExtractError::TooManyAttributes(self.limits.max_attributes, attribute_position));
+					}
+					Self::skip_whitespace(iter)?;
+					Self::expect(iter, XmlToken::Assign)?;
+					Self::skip_whitespace(iter)?;
+					let quote = match iter.next()? {
+						Some(XmlToken::Quote(quote)) => quote,
+						Some(token) => return Err(ExtractError::UnexpectedToken(token, iter.position)),
+						None => return Err(ExtractError::UnexpectedEnd(iter.end)),
+					};
+					let value = match iter.next()? {
+						Some(XmlToken::Value(value)) => value,
+						Some(token) => return Err(ExtractError::UnexpectedToken(token, iter.position)),
+						None => return Err(ExtractError::UnexpectedEnd(iter.end)),
+					};
+					Self::expect(iter, XmlToken::Quote(quote))?;
+					attributes.extend(XmlAttribute::new_quoted(attribute.into_owned(), value.into_owned(), quote).map(|attribute| (attribute, attribute_position)));
+				},
+				Some(XmlToken::Close) => {
+					Self::expect(iter, XmlToken::End)?;
+					let element = self.resolve_namespaces(name, attributes, position)?;
+					self.close_namespaces();
+					self.current().add_child(element);
+					return Ok(());
+				},
+				Some(XmlToken::End) => {
+					let element = self.resolve_namespaces(name, attributes, position)?;
+					self.open.push(Node::new(element));
+					return Ok(());
+				},
+				Some(token) => return Err(ExtractError::UnexpectedToken(token, iter.position)),
+				None => return Err(ExtractError::UnexpectedEnd(iter.end)),
+			}
+		}
+	}
+
+	/// Opens the namespace scope of the element and resolves the prefixes of
+	/// its name and attributes, the scope has to be closed with the element.
+	/// Without namespace processing the names are taken as they are.
+	fn resolve_namespaces(&mut self, name: String, attributes: Vec<(XmlAttribute, XmlPosition)>, position: XmlPosition) -> ExtractResult<XmlElement> {
+		let (mut attributes, positions): (Vec<XmlAttribute>, Vec<XmlPosition>) = attributes.into_iter().unzip();
+		for (index, attribute) in attributes.iter().enumerate() {
+			if attributes[..index].iter().any(|other| other.name == attribute.name) {
+				return Err(ExtractError::DuplicateAttribute(attribute.name.clone(), positions[index]));
+			}
+		}
+		let namespaces = match self.namespaces {
+			Some(ref mut namespaces) => namespaces,
+			None => return Ok(XmlElement::new_element(name, attributes)),
+		};
+		namespaces.push(&attributes, position)?;
+		let mut names: Vec<XmlQName> = vec![];
+		for (attribute, &attribute_position) in attributes.iter_mut().zip(&positions) {
+			let qname = namespaces.resolve_attribute(&attribute.name, attribute_position)?;
+			// different prefixes can be bound to the same namespace
+			if names.iter().any(|other| other.expanded_name() == qname.expanded_name()) {
+				return Err(ExtractError::DuplicateAttribute(attribute.name.clone(), attribute_position));
+			}
+			attribute.namespace = qname.namespace.clone();
+			names.push(qname);
+		}
+		let namespace = namespaces.resolve_element(&name, position)?.namespace;
+		Ok(XmlElement::Element { name, attributes, namespace })
+	}
+
+	fn close_namespaces(&mut self) {
+		if let Some(ref mut namespaces) = self.namespaces {
+			namespaces.pop();
+		}
+	}
+
+	fn parse_end_tag<'a, I: Iterator<Item = XmlTokenResult<'a>>>(&mut self, iter: &mut XmlTokenCursor<I>) -> ExtractResult<()> {
+		let (name, position) = Self::expect_name(iter)?;
+		Self::skip_whitespace(iter)?;
+		Self::expect(iter, XmlToken::End)?;
+		let node = match self.open.pop() {
+			Some(node) => node,
+			None => return Err(ExtractError::UnexpectedToken(XmlToken::new_name(name), position)),
+		};
+		if node.get_data().name() != Some(name.as_str()) {
+			return Err(ExtractError::MismatchedTag(node.get_data().name().unwrap_or_default().to_string(), name, position));
+		}
+		self.close_namespaces();
+		self.current().add_node(node);
+		Ok(())
+	}
+
+	fn expect_name<'a, I: Iterator<Item = XmlTokenResult<'a>>>(iter: &mut XmlTokenCursor<I>) -> ExtractResult<(String, XmlPosition)> {
+		match iter.next()? {
+			Some(XmlToken::Name(name)) => Ok((name.into_owned(), iter.position)),
+			Some(token) => Err(ExtractError::UnexpectedToken(token, iter.position)),
+			None => Err(ExtractError::UnexpectedEnd(iter.end)),
+		}
+	}
+
+	fn expect<'a, I: Iterator<Item = XmlTokenResult<'a>>>(iter: &mut XmlTokenCursor<I>, expected: XmlToken<'static>) -> ExtractResult<()> {
+		match iter.next()? {
+			Some(ref token) if *token == expected => Ok(()),
+			Some(token) => Err(ExtractError::UnexpectedToken(token, iter.position)),
+			None => Err(ExtractError::UnexpectedEnd(iter.end)),
+		}
+	}
+
+	/// Skips whitespace tokens, returns whether there were any.
+	fn skip_whitespace<'a, I: Iterator<Item = XmlTokenResult<'a>>>(iter: &mut XmlTokenCursor<I>) -> ExtractResult<bool> {
+		let mut skipped = false;
+		while let Some(&XmlToken::Whitespace(_)) = iter.peek() {
+			iter.next()?;
+			skipped = true;
+		}
+		Ok(skipped)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use attribute::XmlAttribute;
+	use declaration::XmlDeclaration;
+	use doctype::XmlContentSpec;
+	use errors::ExtractError;
+	use namespace::XML_NAMESPACE;
+	use namespace::XMLNS_NAMESPACE;
+	use options::XmlLimits;
+	use options::XmlOptions;
+	use token::QuoteKind;
+	use token::WhitespaceKind;
+	use token::XmlToken;
+	use stream::XmlStreamTokenizer;
+	use tokenizer::XmlTokenize;
+	use tokenizer_iterator::XmlTokenizer;
+	use tree::Hierarchical;
+	use span::XmlPosition;
+	use span::XmlSpan;
+	use tree::Node;
+	use tree::TraversalOrder;
+	use super::*;
+
+	#[test]
+	fn parse_empty() {
+		let tree = "".to_string().tokenize().unwrap().parse().unwrap();
+		assert_eq!(tree.get_data(), &XmlElement::new_document(None));
+		assert_eq!(tree.get_count(), 0);
+	}
+	#[test]
+	fn parse_element() {
+		let tree = "<element/>".to_string().tokenize().unwrap().parse().unwrap();
+		let mut expected = Node::new(XmlElement::new_document(None));
+		expected.add_child(XmlElement::new_element("element".to_string(), vec![]));
+		assert_eq!(tree, expected);
+	}
+	#[test]
+	fn parse_element_in_element() {
+		let tree = "<element>\n\t<level/>\n</element>".to_string().tokenize().unwrap().parse().unwrap();
+		let mut expected = Node::new(XmlElement::new_document(None));
+		{
+			let element = expected.add_child(XmlElement::new_element("element".to_string(), vec![]));
+			element.add_child(XmlElement::Whitespace(XmlWhitespace::new("\n\t".to_string()).unwrap()));
+			element.add_child(XmlElement::new_element("level".to_string(), vec![]));
+			element.add_child(XmlElement::Whitespace(XmlWhitespace::from(WhitespaceKind::LF(1))));
+		}
+		assert_eq!(tree, expected);
+	}
+	#[test]
+	fn parse_attributes() {
+		let tree = "<element first=\"1\" second = '2'/>".to_string().tokenize().unwrap().parse().unwrap();
+		let element = tree.get_child_data(0);
+		assert_eq!(element.name(), Some("element"));
+		assert_eq!(element.attributes(), &[
+			XmlAttribute::new("first".to_string(), "1".to_string()).unwrap(),
+			XmlAttribute::new_quoted("second".to_string(), "2".to_string(), QuoteKind::Single).unwrap(),
+		]);
+		assert_eq!(element.attribute("second"), Some("2"));
+		assert_eq!(element.attribute("third"), None);
+	}
+	#[test]
+	fn parse_text() {
+		let mut tree = "<p>Hello, world! <b>1 > 0</b></p>".to_string().tokenize().unwrap().parse().unwrap();
+		assert_eq!(tree.get_child(0).get_count(), 2);
+		assert_eq!(tree.get_child(0).get_child_data(0), &XmlElement::new_text("Hello, world! ".to_string()));
+		assert_eq!(tree.get_child(0).get_child(1).get_child_data(0), &XmlElement::new_text("1 > 0".to_string()));
+	}
+	#[test]
+	fn parse_declaration() {
+		let tree = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<a/>".to_string().tokenize().unwrap().parse().unwrap();
+		let declaration = XmlDeclaration::new("1.0".to_string(), Some("UTF-8".to_string()), None);
+		assert_eq!(tree.get_data(), &XmlElement::new_document(Some(declaration.clone())));
+		assert_eq!(tree.get_data().declaration(), Some(&declaration));
+		assert_eq!(tree.get_count(), 2);
+	}
+	#[test]
+	fn parse_misplaced_declaration() {
+		let mut tokens = "<a/>".to_string().tokenize().unwrap();
+		tokens.extend("<?xml version=\"1.0\"?>".to_string().tokenize().unwrap());
+		assert!(matches!(tokens.parse(), Err(ExtractError::MisplacedDeclaration(_))));
+	}
+	#[test]
+	fn parse_doctype() {
+		let text = "<?xml version=\"1.0\"?>\n<!-- note -->\n<!DOCTYPE a SYSTEM \"a.dtd\" [<!ELEMENT a (#PCDATA)>]>\n<a/>";
+		let tree = text.tokenize().unwrap().parse().unwrap();
+		let doctype = tree.get_data().doctype().unwrap();
+		assert_eq!(doctype.name, "a");
+		assert_eq!(doctype.element("a").unwrap().content, XmlContentSpec::Mixed(vec![]));
+		assert_eq!(tree.get_count(), 5);
+	}
+	#[test]
+	fn parse_namespaces() {
+		let options = XmlOptions::new().with_namespace_aware(true);
+		let first = "<soap:Envelope xmlns:soap=\"urn:soap\"><soap:Body/></soap:Envelope>".tokenize().unwrap().parse_with(&options).unwrap();
+		let mut second = "<s:Envelope xmlns:s=\"urn:soap\" xmlns=\"urn:app\" s:mustUnderstand=\"1\" id=\"a\"><Body xml:lang=\"en\"/></s:Envelope>".tokenize().unwrap().parse_with(&options).unwrap();
+		let first_name = first.get_child_data(0).qname().unwrap();
+		let second_name = second.get_child_data(0).qname().unwrap();
+		assert_ne!(first_name, second_name);
+		assert_eq!(first_name.expanded_name(), second_name.expanded_name());
+		assert_eq!(second_name.prefix, Some("s".to_string()));
+		let envelope = second.get_child(0);
+		let attributes: Vec<XmlQName> = envelope.get_data().attributes().iter().map(|attribute| attribute.qname()).collect();
+		assert_eq!(attributes[0].expanded_name(), (Some(XMLNS_NAMESPACE), "s"));
+		assert_eq!(attributes[2].expanded_name(), (Some("urn:soap"), "mustUnderstand"));
+		assert_eq!(attributes[3].expanded_name(), (None, "id"));
+		let body = envelope.get_child_data(0);
+		assert_eq!(body.qname().unwrap().expanded_name(), (Some("urn:app"), "Body"));
+		assert_eq!(body.attributes()[0].qname().expanded_name(), (Some(XML_NAMESPACE), "lang"));
+	}
+	#[test]
+	fn parse_into_document() {
+		let tree = "<a><b/><c><d/></c></a>".tokenize().unwrap().parse().unwrap();
+		let document = XmlDocument::from(tree.clone());
+		let names = |ids: Vec<_>| ids.into_iter().map(|id| document.get(id).name().unwrap()).collect::<Vec<_>>();
+		let d = document.descendants(document.root()).last().unwrap();
+		assert_eq!(document.get(d).name(), Some("d"));
+		assert_eq!(names(document.ancestors(d).take(2).collect()), vec!["c", "a"]);
+		assert_eq!(document.parent(document.ancestors(d).nth(1).unwrap()), Some(document.root()));
+		assert_eq!(names(document.preceding(d).collect()), vec!["b"]);
+		assert_eq!(document.to_node(document.root()), tree);
+	}
+	#[test]
+	fn parse_undeclared_prefix() {
+		let options = XmlOptions::new().with_namespace_aware(true);
+		match "<r><a xmlns:p=\"urn:p\"><p:b/></a><p:c/></r>".tokenize().unwrap().parse_with(&options) {
+			Err(ExtractError::UndeclaredPrefix(prefix, position)) => {
+				assert_eq!(prefix, "p");
+				assert_eq!(position.offset, 33);
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!("<a p:b=\"c\"/>".tokenize().unwrap().parse_with(&options), Err(ExtractError::UndeclaredPrefix(_, _))));
+	}
+	#[test]
+	fn parse_unexpected_end_after_references() {
+		let text = "<a>&#x26;&#x26;&#x26;&#x26;";
+		match text.tokenize().unwrap().parse() {
+			Err(ExtractError::UnexpectedEnd(position)) => assert_eq!(position, XmlPosition::new(27, 1, 28)),
+			other => panic!("unexpected result {:?}", other),
+		}
+		match "<a b='&lt;\r\n'".tokenize().unwrap().parse() {
+			Err(ExtractError::UnexpectedEnd(position)) => assert_eq!(position, XmlPosition::new(13, 2, 2)),
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn parse_duplicate_attributes() {
+		match "<a x='1' x='2'/>".tokenize().unwrap().parse() {
+			Err(ExtractError::DuplicateAttribute(name, position)) => {
+				assert_eq!(name, "x");
+				assert_eq!(position.offset, 9);
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		let text = "<a xmlns:p=\"urn:a\" xmlns:q=\"urn:a\" p:x=\"1\" q:x=\"2\"/>";
+		assert!(text.tokenize().unwrap().parse().is_ok());
+		let options = XmlOptions::new().with_namespace_aware(true);
+		assert!(matches!(text.tokenize().unwrap().parse_with(&options), Err(ExtractError::DuplicateAttribute(ref name, _)) if name == "q:x"));
+		assert!("<a xmlns:p=\"urn:a\" xmlns:q=\"urn:b\" p:x=\"1\" q:x=\"2\"/>".tokenize().unwrap().parse_with(&options).is_ok());
+	}
+	#[test]
+	fn parse_without_namespaces() {
+		for text in &["<:root/>", "<a:b:c/>", "<p:a q:b=\"1\"/>"] {
+			let tree = text.tokenize().unwrap().parse().unwrap();
+			assert_eq!(tree.get_child_data(0).name(), Some(&text[1..text.find([' ', '/']).unwrap()]));
+			assert_eq!(tree.get_child_data(0).qname().unwrap().namespace, None);
+		}
+		let options = XmlOptions::new().with_namespace_aware(true);
+		assert!(matches!("<:root/>".tokenize().unwrap().parse_with(&options), Err(ExtractError::InvalidQName(_, _))));
+		assert!(matches!("<a:b:c/>".tokenize().unwrap().parse_with(&options), Err(ExtractError::InvalidQName(_, _))));
+	}
+	#[test]
+	fn parse_with_limits() {
+		let options = XmlOptions::new().with_limits(XmlLimits { max_depth: 2, ..XmlLimits::default() });
+		assert!(XmlTokenizer::with_options("<a><b/></a>".chars(), &options).parse().is_ok());
+		assert!(matches!(XmlTokenizer::with_options("<a><b><c/></b></a>".chars(), &options).parse(), Err(ExtractError::NestingTooDeep(2, _))));
+	}
+	#[test]
+	fn parse_with_default_limits() {
+		let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+		assert!(nested(256).tokenize().unwrap().parse().is_ok());
+		assert!(matches!(nested(257).tokenize(), Err(ExtractError::NestingTooDeep(256, _))));
+		assert!(matches!(XmlTokenizer::new(nested(100_000).chars()).parse(), Err(ExtractError::NestingTooDeep(256, _))));
+		let tokens = XmlTokenizer::with_options(nested(257).chars(), &XmlOptions::new().with_limits(XmlLimits::unlimited())).collect::<ExtractResult<XmlTokenVec>>().unwrap();
+		assert!(matches!(tokens.parse(), Err(ExtractError::NestingTooDeep(256, _))));
+	}
+	#[test]
+	fn parse_tokens_with_limits() {
+		// tokens that did not come from a limited tokenizer
+		let element = |depth: usize, attributes: usize| {
+			let mut tokens = vec![XmlToken::Begin, XmlToken::new_name("a")];
+			for index in 0..attributes {
+				tokens.extend(vec![XmlToken::new_whitespace(" ".to_string()).unwrap(), XmlToken::new_name(format!("b{}", index)), XmlToken::Assign, XmlToken::Quote(QuoteKind::Double), XmlToken::Value("".into()), XmlToken::Quote(QuoteKind::Double)]);
+			}
+			tokens.extend(vec![XmlToken::Close, XmlToken::End]);
+			let mut nested = vec![];
+			for _ in 0..depth - 1 {
+				nested.extend(vec![XmlToken::Begin, XmlToken::new_name("e"), XmlToken::End]);
+			}
+			nested.extend(tokens);
+			for _ in 0..depth - 1 {
+				nested.extend(vec![XmlToken::Begin, XmlToken::Close, XmlToken::new_name("e"), XmlToken::End]);
+			}
+			nested.into_iter().map(|token| XmlSpanned::new(token, XmlSpan::between(XmlPosition::start(), XmlPosition::start()))).collect::<XmlTokenVec>()
+		};
+		let options = XmlOptions::new().with_limits(XmlLimits { max_depth: 3, max_attributes: 2, ..XmlLimits::default() });
+		assert!(element(3, 1).parse_with(&options).is_ok());
+		assert!(element(2, 3).parse().is_ok());
+		assert!(matches!(element(4, 0).parse_with(&options), Err(ExtractError::NestingTooDeep(3, _))));
+		assert!(matches!(element(1, 3).parse_with(&options), Err(ExtractError::TooManyAttributes(2, _))));
+	}
+	#[test]
+	fn parse_single_root() {
+		assert!(matches!("<a/><b/>".tokenize().unwrap().parse(), Err(ExtractError::MultipleRoots(position)) if position.offset == 4));
+		assert!(matches!("<a/>text".tokenize().unwrap().parse(), Err(ExtractError::TextOutsideRoot(position)) if position.offset == 4));
+		assert!(matches!("&amp;<a/>".tokenize().unwrap().parse(), Err(ExtractError::TextOutsideRoot(_))));
+		assert!(matches!("<![CDATA[a]]><a/>".tokenize().unwrap().parse(), Err(ExtractError::TextOutsideRoot(_))));
+		let tree = "<!-- a -->\n<a/>\n<?pi?>\n".tokenize().unwrap().parse().unwrap();
+		assert_eq!(tree.get_count(), 6);
+	}
+	#[test]
+	fn parse_attributes_without_whitespace() {
+		match "<a x=\"1\"y=\"2\"/>".tokenize().unwrap().parse() {
+			Err(ExtractError::UnexpectedToken(token, position)) => {
+				assert_eq!(token, XmlToken::new_name("y"));
+				assert_eq!(position.offset, 8);
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn parse_deep_nesting() {
+		let depth = 10_000;
+		let text = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+		let options = XmlOptions::new().with_limits(XmlLimits { max_depth: depth, ..XmlLimits::default() });
+		let tree = XmlTokenizer::with_options(text.chars(), &options).parse_with(&options).unwrap();
+		assert_eq!(tree.descendants(TraversalOrder::PreOrder).count(), depth);
+	}
+	#[test]
+	fn parse_misplaced_doctype() {
+		assert!(matches!("<a/><!DOCTYPE a>".tokenize().unwrap().parse(), Err(ExtractError::MisplacedDoctype(_))));
+		assert!(matches!("<a><!DOCTYPE a></a>".tokenize().unwrap().parse(), Err(ExtractError::MisplacedDoctype(_))));
+		assert!(matches!("<!DOCTYPE a><!DOCTYPE a><a/>".tokenize().unwrap().parse(), Err(ExtractError::MisplacedDoctype(_))));
+	}
+	#[test]
+	fn parse_markup() {
+		let tree = "<?style href=\"a\"?><a><!-- note --><![CDATA[<b/>]]>text</a>".to_string().tokenize().unwrap().parse().unwrap();
+		let mut expected = Node::new(XmlElement::new_document(None));
+		expected.add_child(XmlElement::ProcessingInstruction { target: "style".to_string(), data: "href=\"a\"".to_string() });
+		{
+			let element = expected.add_child(XmlElement::new_element("a".to_string(), vec![]));
+			element.add_child(XmlElement::Comment(" note ".to_string()));
+			element.add_child(XmlElement::CData("<b/>".to_string()));
+			element.add_child(XmlElement::new_text("text".to_string()));
+		}
+		assert_eq!(tree, expected);
+	}
+	#[test]
+	fn parse_tokenizer() {
+		let tree = XmlTokenizer::new("<a><b/></a>".chars()).parse().unwrap();
+		let mut expected = Node::new(XmlElement::new_document(None));
+		expected.add_child(XmlElement::new_element("a".to_string(), vec![]))
+			.add_child(XmlElement::new_element("b".to_string(), vec![]));
+		assert_eq!(tree, expected);
+	}
+	#[test]
+	fn parse_stream_tokenizer() {
+		let tree = XmlStreamTokenizer::from_read("<a><b/></a>".as_bytes()).parse().unwrap();
+		assert_eq!(tree, "<a><b/></a>".to_string().tokenize().unwrap().parse().unwrap());
+	}
+	#[test]
+	fn parse_tokenizer_error() {
+		match XmlTokenizer::new("<a b=\"c></a>".chars()).parse() {
+			Err(ExtractError::UnterminatedValue(c, _)) => assert_eq!(c, '"'),
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn parse_references() {
+		let mut tree = "<p title=\"&quot;a&quot;\">fish &amp; chips</p>".to_string().tokenize().unwrap().parse().unwrap();
+		assert_eq!(tree.get_child_data(0).attribute("title"), Some("\"a\""));
+		assert_eq!(tree.get_child(0).get_child_data(0), &XmlElement::new_text("fish & chips".to_string()));
+	}
+	#[test]
+	fn parse_mismatched_end_tag() {
+		match "<a></b>".to_string().tokenize().unwrap().parse() {
+			Err(ExtractError::MismatchedTag(expected, found, position)) => {
+				assert_eq!(expected, "a");
+				assert_eq!(found, "b");
+				assert_eq!(position, XmlPosition::new(5, 1, 6));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn parse_unexpected_end_tag() {
+		match "<a/></a>".to_string().tokenize().unwrap().parse() {
+			Err(ExtractError::UnexpectedToken(token, position)) => {
+				assert_eq!(token, XmlToken::new_name("a".to_string()));
+				assert_eq!(position, XmlPosition::new(6, 1, 7));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn parse_unclosed_element() {
+		match "<a><b/>".to_string().tokenize().unwrap().parse() {
+			Err(ExtractError::UnexpectedEnd(position)) => assert_eq!(position, XmlPosition::new(7, 1, 8)),
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct XmlPosition {
+	/// Byte offset from the start of the input.
+	pub offset: usize,
+	/// Line number, starting at 1.
+	pub line: usize,
+	/// Column in characters, starting at 1.
+	pub column: usize,
+}
+
+
+impl XmlPosition {
+	pub fn new(offset: usize, line: usize, column: usize) -> XmlPosition {
+		XmlPosition { offset, line, column }
+	}
+	/// Position of the first character of the input.
+	pub fn start() -> XmlPosition {
+		XmlPosition::new(0, 1, 1)
+	}
+	/// Moves the position past the given character.
+	pub fn advance(&mut self, c: char) {
+		self.offset += c.len_utf8();
+		if c == '\n' {
+			self.line += 1;
+			self.column = 1;
+		}
+		else {
+			self.column += 1;
+		}
+	}
+}
+
+
+impl Default for XmlPosition {
+	fn default() -> Self {
+		XmlPosition::start()
+	}
+}
+
+
+impl fmt::Display for XmlPosition {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}", self.line, self.column)
+	}
+}
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct XmlSpan {
+	/// Position of the first character.
+	pub start: XmlPosition,
+	/// Position just past the last character.
+	pub end: XmlPosition,
+}
+
+
+impl XmlSpan {
+	/// Span covering everything from `start` up to, but not including, `end`.
+	pub fn between(start: XmlPosition, end: XmlPosition) -> XmlSpan {
+		XmlSpan { start, end }
+	}
+	/// Length in bytes.
+	pub fn length(&self) -> usize {
+		self.end.offset - self.start.offset
+	}
+}
+
+
+impl fmt::Display for XmlSpan {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.start)
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlSpanned<T> {
+	pub token: T,
+	pub span: XmlSpan,
+}
+
+
+impl<T> XmlSpanned<T> {
+	pub fn new(token: T, span: XmlSpan) -> XmlSpanned<T> {
+		XmlSpanned { token, span }
+	}
+}
+
+
+impl<T: fmt::Display> fmt::Display for XmlSpanned<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.token)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn advance_position() {
+		let mut position = XmlPosition::start();
+		for c in "ab\nc€".chars() {
+			position.advance(c);
+		}
+		assert_eq!(position, XmlPosition::new(7, 2, 3));
+	}
+	#[test]
+	fn display_position() {
+		assert_eq!(format!("{}", XmlPosition::new(10, 3, 7)), "3:7".to_string());
+	}
+	#[test]
+	fn span_between() {
+		let span = XmlSpan::between(XmlPosition::new(2, 1, 3), XmlPosition::new(9, 2, 1));
+		assert_eq!(span.length(), 7);
+		assert_eq!(span.end, XmlPosition::new(9, 2, 1));
+	}
+}
@@ -1 +1,280 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::str;
+use errors::ExtractError;
+use errors::ExtractResult;
+use options::XmlOptions;
+use span::XmlPosition;
+use span::XmlSpanned;
+use token::XmlToken;
+use tokenizer_iterator::XmlTokenizer;
 
+
+/// Decodes UTF-8 characters from a buffered reader, one buffer at a time.
+///
+/// Iteration stops at the first read or decoding error, which is kept in
+/// `error` until it is taken by the tokenizer.
+pub struct XmlCharReader<R> {
+	reader: R,
+	bytes: Vec<u8>, // bytes read but not yet decoded, at most one incomplete sequence between reads
+	chars: VecDeque<char>, // decoded characters not yet returned
+	invalid: Option<u8>, // first byte of an invalid sequence following the decoded characters
+	position: XmlPosition, // position of the next character
+	done: bool,
+	pub error: Option<ExtractError>,
+}
+
+
+impl<R: BufRead> XmlCharReader<R> {
+	pub fn new(reader: R) -> XmlCharReader<R> {
+		XmlCharReader {
+			reader,
+			bytes: vec![],
+			chars: VecDeque::new(),
+			invalid: None,
+			position: XmlPosition::start(),
+			done: false,
+			error: None,
+		}
+	}
+
+	fn fill(&mut self) {
+		let length = match self.reader.fill_buf() {
+			Ok(buffer) => {
+				self.bytes.extend_from_slice(buffer);
+				buffer.len()
+			},
+			Err(ref e) if e.kind() == ErrorKind::Interrupted => return,
+			Err(e) => {
+				self.error = Some(ExtractError::Io(e.kind(), self.position));
+				self.done = true;
+				return;
+			},
+		};
+		self.reader.consume(length);
+		if length == 0 {
+			self.done = true;
+			self.invalid = self.bytes.first().cloned();
+			return;
+		}
+		let valid = match str::from_utf8(&self.bytes) {
+			Ok(_) => self.bytes.len(),
+			Err(e) => {
+				if e.error_len().is_some() {
+					self.invalid = Some(self.bytes[e.valid_up_to()]);
+					self.done = true;
+				}
+				e.valid_up_to()
+			},
+		};
+		self.chars.extend(str::from_utf8(&self.bytes[..valid]).unwrap().chars());
+		self.bytes.drain(..valid);
+	}
+}
+
+
+impl<R: BufRead> Iterator for XmlCharReader<R> {
+	type Item = char;
+	fn next(&mut self) -> Option<char> {
+		loop {
+			if let Some(c) = self.chars.pop_front() {
+				self.position.advance(c);
+				return Some(c);
+			}
+			if self.done {
+				if let Some(byte) = self.invalid.take() {
+					self.error = Some(ExtractError::InvalidUtf8(byte, self.position));
+				}
+				return None;
+			}
+			self.fill();
+		}
+	}
+}
+
+
+/// Tokenizer pulling its input from a reader, holding only the token that is
+/// currently being parsed in memory.
+pub struct XmlStreamTokenizer<R> {
+	tokenizer: XmlTokenizer<'static, XmlCharReader<R>>,
+	error: Option<ExtractError>, // read error held back until the token before it was returned
+	done: bool,
+}
+
+
+impl<R: BufRead> XmlStreamTokenizer<R> {
+	pub fn new(reader: R) -> XmlStreamTokenizer<R> {
+		XmlStreamTokenizer::with_options(reader, &XmlOptions::default())
+	}
+	pub fn with_options(reader: R, options: &XmlOptions) -> XmlStreamTokenizer<R> {
+		XmlStreamTokenizer {
+			tokenizer: XmlTokenizer::with_options(XmlCharReader::new(reader), options),
+			error: None,
+			done: false,
+		}
+	}
+}
+
+
+impl<R: Read> XmlStreamTokenizer<BufReader<R>> {
+	pub fn from_read(reader: R) -> XmlStreamTokenizer<BufReader<R>> {
+		XmlStreamTokenizer::new(BufReader::new(reader))
+	}
+}
+
+
+impl<R: BufRead> Iterator for XmlStreamTokenizer<R> {
+	type Item = ExtractResult<XmlSpanned<XmlToken<'static>>>;
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return self.error.take().map(Err);
+		}
+		let token = self.tokenizer.next();
+		// a read error ends the input early, report it instead of what the tokenizer made of that,
+		// but only after a token that was complete before the error
+		if let Some(error) = self.tokenizer.get_mut().error.take() {
+			self.done = true;
+			match token {
+				Some(Ok(ref spanned)) if !is_open_ended(&spanned.token) => {
+					self.error = Some(error);
+					return token;
+				},
+				_ => return Some(Err(error)),
+			}
+		}
+		token
+	}
+}
+
+
+/// Tokens that end where the next character does not belong to them, so they
+/// may have gone on past a read error.
+fn is_open_ended(token: &XmlToken) -> bool {
+	matches!(*token, XmlToken::Name(_) | XmlToken::Text(_) | XmlToken::Whitespace(_))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io::BufReader;
+	use std::io::Cursor;
+	use std::io::Error;
+	use std::io::ErrorKind;
+	use std::io::Read;
+	use std::io::Result;
+	use errors::ExtractError;
+	use errors::ExtractResult;
+	use options::XmlLimits;
+	use span::XmlPosition;
+	use token::XmlToken;
+	use tokenizer::XmlTokenize;
+	use super::*;
+
+	struct FailingReader;
+
+	/// Returns the text, then fails.
+	struct BreakingReader(&'static [u8]);
+
+	impl Read for BreakingReader {
+		fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+			if self.0.is_empty() {
+				return Err(Error::new(ErrorKind::ConnectionReset, "reset"));
+			}
+			let length = self.0.len().min(buffer.len());
+			buffer[..length].copy_from_slice(&self.0[..length]);
+			self.0 = &self.0[length..];
+			Ok(length)
+		}
+	}
+
+	impl Read for FailingReader {
+		fn read(&mut self, _buffer: &mut [u8]) -> Result<usize> {
+			Err(Error::new(ErrorKind::BrokenPipe, "broken"))
+		}
+	}
+
+	#[test]
+	fn char_reader_split_sequences() {
+		let reader = XmlCharReader::new(BufReader::with_capacity(1, "a€ä𝄞".as_bytes()));
+		assert_eq!(reader.collect::<String>(), "a€ä𝄞".to_string());
+	}
+	#[test]
+	fn char_reader_invalid_utf8() {
+		let mut reader = XmlCharReader::new(Cursor::new(vec![b'a', b'b', 0xFF, b'c']));
+		assert_eq!(reader.by_ref().collect::<String>(), "ab".to_string());
+		match reader.error {
+			Some(ExtractError::InvalidUtf8(byte, position)) => {
+				assert_eq!(byte, 0xFF);
+				assert_eq!(position, XmlPosition::new(2, 1, 3));
+			},
+			other => panic!("unexpected error {:?}", other),
+		}
+	}
+	#[test]
+	fn char_reader_truncated_utf8() {
+		let mut reader = XmlCharReader::new(Cursor::new(vec![b'a', 0xE2, 0x82]));
+		assert_eq!(reader.by_ref().collect::<String>(), "a".to_string());
+		assert!(matches!(reader.error, Some(ExtractError::InvalidUtf8(0xE2, _))));
+	}
+	#[test]
+	fn stream_tokenizer_matches_tokenize() {
+		let text = "<element attribute=\"v€lue\">\n\t<level/>\n</element>";
+		let expected = text.to_string().tokenize().unwrap();
+		let tokens: ExtractResult<Vec<_>> = XmlStreamTokenizer::new(BufReader::with_capacity(1, text.as_bytes())).collect();
+		assert_eq!(tokens.unwrap(), expected);
+	}
+	#[test]
+	fn stream_tokenizer_from_read() {
+		let mut tokenizer = XmlStreamTokenizer::from_read("<a/>".as_bytes());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_begin());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_name("a".to_string()));
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_close());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_end());
+		assert!(tokenizer.next().is_none());
+	}
+	#[test]
+	fn stream_tokenizer_error() {
+		let mut tokenizer = XmlStreamTokenizer::from_read("<a b=\"c/>".as_bytes());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_begin());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_name("a".to_string()));
+		tokenizer.next();
+		tokenizer.next();
+		tokenizer.next();
+		assert!(matches!(tokenizer.next(), Some(Err(ExtractError::UnterminatedValue('"', _)))));
+		assert!(tokenizer.next().is_none());
+	}
+	#[test]
+	fn stream_tokenizer_document_size_limit() {
+		let options = XmlOptions::new().with_limits(XmlLimits { max_document_size: 8, ..XmlLimits::default() });
+		let mut reader = BufReader::with_capacity(1, "<a>text that goes on and on</a>".as_bytes());
+		let tokens: ExtractResult<Vec<_>> = XmlStreamTokenizer::with_options(&mut reader, &options).collect();
+		assert!(matches!(tokens, Err(ExtractError::DocumentTooLarge(8, _))));
+		// nothing past the limit and the character that hit it was read
+		assert_eq!(reader.bytes().count(), 22);
+	}
+	#[test]
+	fn stream_tokenizer_read_error_after_token() {
+		let mut tokenizer = XmlStreamTokenizer::from_read(BreakingReader(b"<a><!--c-->"));
+		let tokens: Vec<XmlToken> = tokenizer.by_ref().take(4).map(|token| token.unwrap().token).collect();
+		assert_eq!(tokens, vec![XmlToken::new_begin(), XmlToken::new_name("a".to_string()), XmlToken::new_end(), XmlToken::Comment("c".into())]);
+		match tokenizer.next() {
+			Some(Err(ExtractError::Io(ErrorKind::ConnectionReset, position))) => assert_eq!(position.offset, 11),
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(tokenizer.next().is_none());
+		// the name may go on after the error
+		let mut tokenizer = XmlStreamTokenizer::from_read(BreakingReader(b"<ab"));
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_begin());
+		assert!(matches!(tokenizer.next(), Some(Err(ExtractError::Io(ErrorKind::ConnectionReset, _)))));
+		assert!(tokenizer.next().is_none());
+	}
+	#[test]
+	fn stream_tokenizer_read_error() {
+		let mut tokenizer = XmlStreamTokenizer::from_read(FailingReader);
+		assert!(matches!(tokenizer.next(), Some(Err(ExtractError::Io(ErrorKind::BrokenPipe, _)))));
+		assert!(tokenizer.next().is_none());
+	}
+}
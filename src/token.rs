@@ -1,4 +1,10 @@
 // use attribute::XmlAttribute;
+use std::borrow::Cow;
+use std::fmt;
+use declaration::XmlDeclaration;
+use doctype::XmlDoctype;
+use entity;
+use span::XmlSpanned;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)] 
 pub enum WhitespaceKind {
@@ -19,13 +25,11 @@ impl WhitespaceKind {
 			LF(_c) => '\n',
 		}
 	}
-	pub fn to_string(self) -> String {
+	/// Number of characters in the run.
+	pub fn count(self) -> usize {
 		use token::WhitespaceKind::*;
 		match self {
-			Space(c) => self.to_char().to_string().repeat(c),
-			Tab(c) => self.to_char().to_string().repeat(c),
-			CR(c) => self.to_char().to_string().repeat(c),
-			LF(c) => self.to_char().to_string().repeat(c),
+			Space(c) | Tab(c) | CR(c) | LF(c) => c,
 		}
 	}
 	pub fn from_char(value: char, count: usize) -> Option<Self> {
@@ -38,17 +42,126 @@ impl WhitespaceKind {
 			_ => None
 		}
 	}
+	/// `None` unless the string is one character repeated, use `XmlWhitespace`
+	/// for anything else.
 	pub fn from_string(value: String) -> Option<Self> {
-		if value.len() > 0 {
-			WhitespaceKind::from_char(value.chars().next().unwrap(), value.len())
+		let first = value.chars().next()?;
+		if !value.chars().all(|c| c == first) {
+			return None;
 		}
-		else {
-			None
+		WhitespaceKind::from_char(first, value.chars().count())
+	}
+}
+
+
+impl fmt::Display for WhitespaceKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		use token::WhitespaceKind::*;
+		match *self {
+			Space(c) | Tab(c) | CR(c) | LF(c) => write!(f, "{}", self.to_char().to_string().repeat(c)),
+		}
+	}
+}
+
+
+
+/// Run of spaces, tabs and line breaks in any order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XmlWhitespace {
+	text: String,
+}
+
+
+impl XmlWhitespace {
+	/// `None` when the text is empty or contains anything but XML whitespace.
+	pub fn new(text: String) -> Option<XmlWhitespace> {
+		if text.is_empty() || text.chars().any(|c| WhitespaceKind::from_char(c, 1).is_none()) {
+			return None;
+		}
+		Some(XmlWhitespace { text })
+	}
+	pub fn as_str(&self) -> &str {
+		&self.text
+	}
+	/// The whitespace split into runs of the same character.
+	pub fn runs(&self) -> Vec<WhitespaceKind> {
+		let mut runs: Vec<WhitespaceKind> = vec![];
+		for c in self.text.chars() {
+			match runs.last_mut() {
+				Some(run) if run.to_char() == c => *run = WhitespaceKind::from_char(c, run.count() + 1).unwrap(),
+				_ => runs.push(WhitespaceKind::from_char(c, 1).unwrap()),
+			}
+		}
+		runs
+	}
+	/// Number of line breaks, `\r\n` counts as one.
+	pub fn line_breaks(&self) -> usize {
+		self.text.matches('\n').count() + self.text.matches('\r').count() - self.text.matches("\r\n").count()
+	}
+	/// The whitespace following the last line break, all of it when there is none.
+	pub fn indentation(&self) -> &str {
+		match self.text.rfind(['\n', '\r']) {
+			Some(index) => &self.text[index + 1..],
+			None => &self.text,
+		}
+	}
+	/// Width of the indentation in columns, with tab stops every `tab_width` columns.
+	pub fn indentation_width(&self, tab_width: usize) -> usize {
+		self.indentation().chars().fold(0, |width, c| match c {
+			'\t' if tab_width > 0 => (width / tab_width + 1) * tab_width,
+			'\t' => width,
+			_ => width + 1,
+		})
+	}
+}
+
+
+impl From<WhitespaceKind> for XmlWhitespace {
+	fn from(kind: WhitespaceKind) -> XmlWhitespace {
+		XmlWhitespace { text: kind.to_string() }
+	}
+}
+
+
+impl fmt::Display for XmlWhitespace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.text)
+	}
+}
+
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QuoteKind {
+	Double, // "
+	Single, // '
+}
+
+
+impl QuoteKind {
+	pub fn to_char(self) -> char {
+		match self {
+			QuoteKind::Double => '\"',
+			QuoteKind::Single => '\'',
+		}
+	}
+	pub fn from_char(value: char) -> Option<Self> {
+		match value {
+			'\"' => Some(QuoteKind::Double),
+			'\'' => Some(QuoteKind::Single),
+			_ => None
 		}
 	}
 }
 
 
+impl fmt::Display for QuoteKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_char())
+	}
+}
+
+
 
 #[derive(Debug, Copy, Clone, PartialEq)] 
 pub enum SymbolKind {
@@ -56,85 +169,157 @@ pub enum SymbolKind {
 }
 
 
+/// Token of the input, text is borrowed from the input when it appears there
+/// unchanged and owned otherwise, for example when references were decoded.
 #[derive(Debug, Clone, PartialEq, Eq)] 
-pub enum XmlToken {
-	Whitespace(WhitespaceKind), // any type of whitespace
+pub enum XmlToken<'a> {
+	Whitespace(XmlWhitespace), // any sequence of whitespace
 	Begin, // <
-	Name(String), // name
+	Name(Cow<'a, str>), // name
 	Close, // /
 	End, // >
-	Value(String), // "value"
+	Value(Cow<'a, str>), // "value"
 	Assign, // =
-	Quote, // "
-	Text(String), // any text
-	Other(String), // future reference dtd etc
+	Quote(QuoteKind), // " or '
+	Text(Cow<'a, str>), // any text
+	Comment(Cow<'a, str>), // <!-- comment -->
+	CData(Cow<'a, str>), // <![CDATA[ text ]]>
+	ProcessingInstruction(Cow<'a, str>, Cow<'a, str>), // <?target data?>
+	Declaration(XmlDeclaration), // <?xml version="1.0"?>
+	Doctype(XmlDoctype), // <!DOCTYPE name [ declarations ]>
 }
 
-type OptionalXmlToken = Option<XmlToken>;
+type OptionalXmlToken<'a> = Option<XmlToken<'a>>;
 
 
-impl XmlToken {
-	pub fn new_whitespace(value: String) -> OptionalXmlToken {
-		let s = WhitespaceKind::from_string(value);
-		s.and_then(|c| Some(XmlToken::Whitespace(c)))
+impl<'a> XmlToken<'a> {
+	pub fn new_whitespace(value: String) -> OptionalXmlToken<'a> {
+		XmlWhitespace::new(value).map(XmlToken::Whitespace)
 	}
-	pub fn new_name(name: String) -> XmlToken {
-		XmlToken::Name(name)
+	pub fn new_name<S: Into<Cow<'a, str>>>(name: S) -> XmlToken<'a> {
+		XmlToken::Name(name.into())
 	}
-	pub fn new_begin() -> XmlToken {
+	pub fn new_begin() -> XmlToken<'a> {
 		XmlToken::Begin
 	}
-	pub fn new_close() -> XmlToken {
+	pub fn new_close() -> XmlToken<'a> {
 		XmlToken::Close
 	}
-	pub fn new_end() -> XmlToken {
+	pub fn new_end() -> XmlToken<'a> {
 		XmlToken::End
 	}
-	pub fn new_value(value: String) -> XmlToken {
-		XmlToken::Value(value)
+	pub fn new_value<S: Into<Cow<'a, str>>>(value: S) -> XmlToken<'a> {
+		XmlToken::Value(value.into())
 	}
-	pub fn new_assign() -> XmlToken {
+	pub fn new_assign() -> XmlToken<'a> {
 		XmlToken::Assign
 	}
-	pub fn new_quote() -> XmlToken {
-		XmlToken::Quote
+	pub fn new_quote() -> XmlToken<'a> {
+		XmlToken::Quote(QuoteKind::Double)
+	}
+	pub fn new_single_quote() -> XmlToken<'a> {
+		XmlToken::Quote(QuoteKind::Single)
+	}
+	pub fn new_text<S: Into<Cow<'a, str>>>(value: S) -> XmlToken<'a> {
+		XmlToken::Text(value.into())
+	}
+	pub fn new_comment<S: Into<Cow<'a, str>>>(value: S) -> XmlToken<'a> {
+		XmlToken::Comment(value.into())
+	}
+	pub fn new_cdata<S: Into<Cow<'a, str>>>(value: S) -> XmlToken<'a> {
+		XmlToken::CData(value.into())
+	}
+	pub fn new_processing_instruction<S: Into<Cow<'a, str>>, T: Into<Cow<'a, str>>>(target: S, data: T) -> XmlToken<'a> {
+		XmlToken::ProcessingInstruction(target.into(), data.into())
 	}
-	pub fn new_text(value: String) -> XmlToken {
-		XmlToken::Text(value)
+	pub fn new_declaration(declaration: XmlDeclaration) -> XmlToken<'a> {
+		XmlToken::Declaration(declaration)
 	}
-	pub fn to_string(self) -> String {
+	pub fn new_doctype(doctype: XmlDoctype) -> XmlToken<'a> {
+		XmlToken::Doctype(doctype)
+	}
+	/// Copy of the token that no longer borrows from the input.
+	pub fn into_owned(self) -> XmlToken<'static> {
+		use token::XmlToken::*;
+		let owned = |s: Cow<'a, str>| Cow::Owned(s.into_owned());
+		match self {
+			Whitespace(w) => Whitespace(w),
+			Begin => Begin,
+			Name(s) => Name(owned(s)),
+			Close => Close,
+			End => End,
+			Value(s) => Value(owned(s)),
+			Assign => Assign,
+			Quote(q) => Quote(q),
+			Text(s) => Text(owned(s)),
+			Comment(s) => Comment(owned(s)),
+			CData(s) => CData(owned(s)),
+			ProcessingInstruction(t, d) => ProcessingInstruction(owned(t), owned(d)),
+			Declaration(d) => Declaration(d),
+			Doctype(d) => Doctype(d),
+		}
+	}
+	/// True when the token holds text that is borrowed from the input.
+	pub fn is_borrowed(&self) -> bool {
 		use token::XmlToken::*;
 		match self {
-			Begin => format!("<"), 
-			Name(s) => format!("{}", s),
-			Close => format!("/"),
-			End => format!(">"),
-			Assign => format!("="),
-			Value(v) => format!("{}", v),
-			Quote => format!("\""),
-			Text(s) => s,
-			Other(s) => s,
-			Whitespace(t) => t.to_string(),
+			Name(s) | Value(s) | Text(s) | Comment(s) | CData(s) => matches!(s, Cow::Borrowed(_)),
+			ProcessingInstruction(t, d) => matches!(t, Cow::Borrowed(_)) || matches!(d, Cow::Borrowed(_)),
+			_ => false,
 		}
 	}
-	pub fn from_char(c: char) -> OptionalXmlToken {
+	pub fn from_char(c: char) -> OptionalXmlToken<'a> {
 		use token::XmlToken::*;
 		match c {
 			'<' => Some(Begin),
 			'>' => Some(End),
 			'=' => Some(Assign),
 			'/' => Some(Close),
-			'\"' => Some(Quote),
+			'\"' | '\'' => QuoteKind::from_char(c).map(Quote),
 			_ => None,
 		}
 	}
 }
 
+
+impl<'a> XmlSpanned<XmlToken<'a>> {
+	/// Copy of the token that no longer borrows from the input.
+	pub fn into_owned(self) -> XmlSpanned<XmlToken<'static>> {
+		XmlSpanned::new(self.token.into_owned(), self.span)
+	}
+}
+
+
+impl<'a> fmt::Display for XmlToken<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		use token::XmlToken::*;
+		match self {
+			Begin => write!(f, "<"),
+			Name(s) => write!(f, "{}", s),
+			Close => write!(f, "/"),
+			End => write!(f, ">"),
+			Assign => write!(f, "="),
+			// the token does not know its delimiter, so both quotes are escaped
+			Value(v) => write!(f, "{}", entity::escape(v)),
+			Quote(q) => write!(f, "{}", q),
+			Text(s) => write!(f, "{}", entity::escape_text(s)),
+			Comment(s) => write!(f, "<!--{}-->", s),
+			CData(s) => write!(f, "<![CDATA[{}]]>", s),
+			ProcessingInstruction(t, d) if d.is_empty() => write!(f, "<?{}?>", t),
+			ProcessingInstruction(t, d) => write!(f, "<?{} {}?>", t, d),
+			Declaration(d) => write!(f, "<?xml {}?>", d),
+			Doctype(d) => write!(f, "<!DOCTYPE {}>", d),
+			Whitespace(t) => write!(f, "{}", t),
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
 	#[test]
+	#[allow(clippy::repeat_once)] // every kind is checked against `repeat`
 	fn space_token() {
 		let whitespace_space = WhitespaceKind::Space(10);
 		assert_eq!(whitespace_space, WhitespaceKind::Space(10));
@@ -156,7 +341,34 @@ mod tests {
 	#[test]
 	fn xml_token_whitespace() {
 		let token = XmlToken::new_whitespace("\t\t\t".to_string());
-		assert_eq!(token, Some(XmlToken::Whitespace(WhitespaceKind::Tab(3))));
+		assert_eq!(token, Some(XmlToken::Whitespace(XmlWhitespace::from(WhitespaceKind::Tab(3)))));
+		assert_eq!(XmlToken::new_whitespace("\t x".to_string()), None);
+		assert_eq!(XmlToken::new_whitespace("".to_string()), None);
+	}
+	#[test]
+	fn whitespace_from_string() {
+		assert_eq!(WhitespaceKind::from_string("   ".to_string()), Some(WhitespaceKind::Space(3)));
+		assert_eq!(WhitespaceKind::from_string("\n\t".to_string()), None);
+		assert_eq!(WhitespaceKind::from_string("".to_string()), None);
+	}
+	#[test]
+	fn mixed_whitespace() {
+		let whitespace = XmlWhitespace::new("\n\t\t  ".to_string()).unwrap();
+		assert_eq!(whitespace.as_str(), "\n\t\t  ");
+		assert_eq!(whitespace.runs(), vec![WhitespaceKind::LF(1), WhitespaceKind::Tab(2), WhitespaceKind::Space(2)]);
+		assert_eq!(whitespace.line_breaks(), 1);
+		assert_eq!(whitespace.indentation(), "\t\t  ");
+		assert_eq!(whitespace.indentation_width(4), 10);
+		assert_eq!(whitespace.to_string(), "\n\t\t  ".to_string());
+	}
+	#[test]
+	fn mixed_whitespace_line_breaks() {
+		let whitespace = XmlWhitespace::new("\r\n\r\n\r \n  \t".to_string()).unwrap();
+		assert_eq!(whitespace.line_breaks(), 4);
+		assert_eq!(whitespace.indentation(), "  \t");
+		assert_eq!(whitespace.indentation_width(8), 8);
+		assert_eq!(XmlWhitespace::new("   ".to_string()).unwrap().indentation_width(4), 3);
+		assert_eq!(XmlWhitespace::new("\n".to_string()).unwrap().indentation(), "");
 	}
 	#[test]
 	fn xml_token_whitespace_to_string() {
@@ -166,7 +378,7 @@ mod tests {
 	#[test]
 	fn xml_token_element() {
 		let token = XmlToken::new_name("element".to_string());
-		assert_eq!(token, XmlToken::Name("element".to_string()));
+		assert_eq!(token, XmlToken::Name("element".into()));
 	}
 	#[test]
 	fn xml_token_element_to_string() {
@@ -196,21 +408,47 @@ mod tests {
 	#[test]
 	fn xml_token_quote() {
 		let token = XmlToken::new_quote();
-		assert_eq!(token, XmlToken::Quote);
+		assert_eq!(token, XmlToken::Quote(QuoteKind::Double));
+		let token = XmlToken::new_single_quote();
+		assert_eq!(token, XmlToken::Quote(QuoteKind::Single));
 	}
 	#[test]
 	fn xml_token_quote_to_string() {
 		let token = XmlToken::new_quote();
 		assert_eq!(token.to_string(), "\"".to_string());
+		let token = XmlToken::new_single_quote();
+		assert_eq!(token.to_string(), "'".to_string());
 	}
 	#[test]
 	fn xml_token_value() {
 		let token = XmlToken::new_value("value".to_string());
-		assert_eq!(token, XmlToken::Value("value".to_string()));
+		assert_eq!(token, XmlToken::Value("value".into()));
 	}
 	#[test]
 	fn xml_token_value_to_string() {
 		let token = XmlToken::new_value("value".to_string());
 		assert_eq!(token.to_string(), "value".to_string());
 	}
-}
\ No newline at end of file
+	#[test]
+	fn xml_token_comment_to_string() {
+		let token = XmlToken::new_comment(" comment ".to_string());
+		assert_eq!(token.to_string(), "<!-- comment -->".to_string());
+	}
+	#[test]
+	fn xml_token_cdata_to_string() {
+		let token = XmlToken::new_cdata("<text>".to_string());
+		assert_eq!(token.to_string(), "<![CDATA[<text>]]>".to_string());
+	}
+	#[test]
+	fn xml_token_processing_instruction_to_string() {
+		let token = XmlToken::new_processing_instruction("target".to_string(), "data".to_string());
+		assert_eq!(token.to_string(), "<?target data?>".to_string());
+	}
+	#[test]
+	fn xml_token_escaped_to_string() {
+		let token = XmlToken::new_value("\"a\" & 'b'".to_string());
+		assert_eq!(token.to_string(), "&quot;a&quot; &amp; &apos;b&apos;".to_string());
+		let token = XmlToken::new_text("\"a\" < b".to_string());
+		assert_eq!(token.to_string(), "\"a\" &lt; b".to_string());
+	}
+}
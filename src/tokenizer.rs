@@ -1,28 +1,54 @@
 ﻿//use std::fmt;
 //use std::ptr;
-use std::str::Chars;
 //use std::error::Error;
-//use errors::ExtractResult;
-//use errors::ExtractError;
+use std::borrow::Cow;
+use errors::ExtractResult;
+use errors::ExtractError;
+use declaration::XmlDeclaration;
+use doctype::XmlDoctype;
+use encoding;
+use entity::XmlEntityExpander;
+use options::XmlLimits;
+use options::XmlLineEndings;
+use options::XmlOptions;
+use span::XmlPosition;
+use span::XmlSpan;
+use span::XmlSpanned;
+use token::QuoteKind;
 use token::WhitespaceKind;
 use token::XmlToken;
+use tokenizer_iterator::XmlTokenizer;
 
 
-type XmlTokenVec = Vec<XmlToken>;
+pub type XmlTokenVec<'a> = Vec<XmlSpanned<XmlToken<'a>>>;
 
 
-pub trait XmlTokenize {
-	fn tokenize(&self) -> XmlTokenVec;
+/// Input that can be split into tokens. Tokens of borrowed input reference
+/// slices of it, owned input gives owned tokens.
+pub trait XmlTokenize<'a> {
+	fn tokenize_with(self, options: &XmlOptions) -> ExtractResult<XmlTokenVec<'a>>;
+	fn tokenize(self) -> ExtractResult<XmlTokenVec<'a>> where Self: Sized {
+		self.tokenize_with(&XmlOptions::default())
+	}
 }
 
-trait XmlParse {
-	fn parse_value(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char>;
-	fn parse_name(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char>;
-	fn parse_whitespace(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char>;
+pub(crate) trait XmlParse<'a> {
+	fn parse_token(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<bool>;
+	fn parse_symbol(&mut self, token: XmlToken<'a>, tokens: &mut XmlTokenVec<'a>);
+	fn parse_markup(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_comment(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_cdata(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_processing_instruction(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_doctype(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_value(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_text(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_name(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
+	fn parse_whitespace(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()>;
 }
 
-trait XmlValidation {
+pub trait XmlValidation {
 	fn is_valid_in_xml(&self) -> bool;
+	fn is_whitespace_in_xml(&self) -> bool;
 	fn is_digit_in_xml(&self) -> bool;
 	fn is_valid_first_char_in_element_name(&self) -> bool;
 	fn is_valid_char_in_element_name(&self) -> bool;
@@ -34,49 +60,69 @@ trait XmlValidation {
 
 impl XmlValidation for char {
 	fn is_valid_in_xml(&self) -> bool {
-		match self {
-			'\u{0001}' ... '\u{D7FF}' |
-			'\u{E000}' ... '\u{FFFD}' => true,
-			_ => false,
-		}
+		matches!(*self,
+			'\u{0009}' |
+			'\u{000A}' |
+			'\u{000D}' |
+			'\u{0020}'..='\u{D7FF}' |
+			'\u{E000}'..='\u{FFFD}' |
+			'\u{10000}'..='\u{10FFFF}')
+	}
+	fn is_whitespace_in_xml(&self) -> bool {
+		matches!(*self,
+			'\u{0020}' |
+			'\u{0009}' |
+			'\u{000A}' |
+			'\u{000D}')
 	}
 	fn is_digit_in_xml(&self) -> bool {
-		match self {
-			'\u{0030}' ... '\u{0039}' | 
-			'\u{0660}' ... '\u{0669}' | 
-			'\u{06F0}' ... '\u{06F9}' | 
-			'\u{0966}' ... '\u{096F}' | 
-			'\u{09E6}' ... '\u{09EF}' | 
-			'\u{0A66}' ... '\u{0A6F}' | 
-			'\u{0AE6}' ... '\u{0AEF}' | 
-			'\u{0B66}' ... '\u{0B6F}' | 
-			'\u{0BE7}' ... '\u{0BEF}' | 
-			'\u{0C66}' ... '\u{0C6F}' | 
-			'\u{0CE6}' ... '\u{0CEF}' | 
-			'\u{0D66}' ... '\u{0D6F}' | 
-			'\u{0E50}' ... '\u{0E59}' | 
-			'\u{0ED0}' ... '\u{0ED9}' | 
-			'\u{0F20}' ... '\u{0F29}' => true,
-			_ => false,
-		}
+		matches!(*self,
+			'\u{0030}'..='\u{0039}' |
+			'\u{0660}'..='\u{0669}' |
+			'\u{06F0}'..='\u{06F9}' |
+			'\u{0966}'..='\u{096F}' |
+			'\u{09E6}'..='\u{09EF}' |
+			'\u{0A66}'..='\u{0A6F}' |
+			'\u{0AE6}'..='\u{0AEF}' |
+			'\u{0B66}'..='\u{0B6F}' |
+			'\u{0BE7}'..='\u{0BEF}' |
+			'\u{0C66}'..='\u{0C6F}' |
+			'\u{0CE6}'..='\u{0CEF}' |
+			'\u{0D66}'..='\u{0D6F}' |
+			'\u{0E50}'..='\u{0E59}' |
+			'\u{0ED0}'..='\u{0ED9}' |
+			'\u{0F20}'..='\u{0F29}')
 	}
+	/// NameStartChar production of XML 1.0 (fifth edition).
 	fn is_valid_first_char_in_element_name(&self) -> bool {
-		match self {
-			'a' ... 'z' |
-			'A' ... 'Z' |
-			'_' => true,
-			_ => false,
-		}
+		matches!(*self,
+			':' |
+			'A'..='Z' |
+			'_' |
+			'a'..='z' |
+			'\u{00C0}'..='\u{00D6}' |
+			'\u{00D8}'..='\u{00F6}' |
+			'\u{00F8}'..='\u{02FF}' |
+			'\u{0370}'..='\u{037D}' |
+			'\u{037F}'..='\u{1FFF}' |
+			'\u{200C}'..='\u{200D}' |
+			'\u{2070}'..='\u{218F}' |
+			'\u{2C00}'..='\u{2FEF}' |
+			'\u{3001}'..='\u{D7FF}' |
+			'\u{F900}'..='\u{FDCF}' |
+			'\u{FDF0}'..='\u{FFFD}' |
+			'\u{10000}'..='\u{EFFFF}')
 	}
+	/// NameChar production of XML 1.0 (fifth edition).
 	fn is_valid_char_in_element_name(&self) -> bool {
-		match self {
-			'a' ... 'z' |
-			'A' ... 'Z' |
-			'0' ... '9' |
-			':' |
-			'_' => true,
-			_ => false,
-		}
+		self.is_valid_first_char_in_element_name() ||
+			self.is_digit_in_xml() ||
+			matches!(*self,
+				'-' |
+				'.' |
+				'\u{00B7}' |
+				'\u{0300}'..='\u{036F}' |
+				'\u{203F}'..='\u{2040}')
 	}
 	fn is_begin(&self) -> bool {
 		*self == '<'
@@ -88,129 +134,523 @@ impl XmlValidation for char {
 		*self == '/'
 	}
 	fn is_quote(&self) -> bool {
-		*self == '\"'
+		matches!(*self, '\"' | '\'')
 	}
 }
 
-impl XmlParse for String {
-	fn parse_value(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char> {
-		let mut next: Option<char>;
-		let mut value: String = String::new();
-		loop {
-			next = iter.next();
-			if next.is_some() {
-				if next.unwrap().is_quote() {
-					break;
-				}
-				else {
-					value.push(next.unwrap());
-				}
+/// Text read from the cursor, a slice of the input for as long as it is
+/// unchanged, a copy after that.
+struct XmlText {
+	start: usize, // offset of the first character
+	owned: Option<String>,
+}
+
+/// Character source with one character lookahead that keeps track of the
+/// position of the next character and whether it is inside a tag.
+pub(crate) struct XmlCursor<'a, I> {
+	pub(crate) iter: I,
+	source: Option<&'a str>, // the input when it is available as a whole
+	peeked: Option<char>,
+	pending: Option<char>, // character read after a lone \r that was normalized
+	skipped: usize, // bytes of a \r dropped in front of the peeked character
+	normalized: bool, // the peeked character differs from the input
+	position: XmlPosition,
+	in_tag: bool,
+	normalize: bool,
+	entities: XmlEntityExpander, // entities declared in the DOCTYPE
+	limits: XmlLimits,
+	truncated: bool, // input stopped at the document size limit
+	depth: usize, // open elements
+	end_tag: bool, // the current tag is an end tag
+	names: usize, // names in the current tag, the element name and its attributes
+	count: usize, // tokens parsed so far
+}
+
+impl<'a, I: Iterator<Item = char>> XmlCursor<'a, I> {
+	/// A leading byte order mark is skipped and not counted in positions.
+	/// With a `source`, `iter` has to yield its characters.
+	pub(crate) fn new(iter: I, source: Option<&'a str>, options: &XmlOptions) -> XmlCursor<'a, I> {
+		let mut cursor = XmlCursor {
+			iter,
+			source,
+			peeked: None,
+			pending: None,
+			skipped: 0,
+			normalized: false,
+			position: XmlPosition::start(),
+			in_tag: false,
+			normalize: options.line_endings == XmlLineEndings::Normalize,
+			entities: XmlEntityExpander::new(options.entity_limits),
+			limits: options.limits,
+			truncated: false,
+			depth: 0,
+			end_tag: false,
+			names: 0,
+			count: 0,
+		};
+		cursor.fill();
+		if cursor.peeked == Some('\u{FEFF}') {
+			cursor.fill();
+			// offsets into the source start after the byte order mark
+			cursor.source = cursor.source.map(|source| &source['\u{FEFF}'.len_utf8()..]);
+		}
+		cursor
+	}
+	fn fill(&mut self) {
+		self.peeked = self.pending.take().or_else(|| self.iter.next());
+		self.normalized = false;
+		if self.normalize && self.peeked == Some('\r') {
+			self.peeked = Some('\n');
+			self.normalized = true;
+			match self.iter.next() {
+				Some('\n') => self.skipped = 1,
+				other => self.pending = other,
 			}
-			else {
-				break;
+		}
+		// input past the limit is never read, whatever is parsed from there is reported as too large
+		if let Some(c) = self.peeked {
+			if self.position.offset + self.skipped + c.len_utf8() > self.limits.max_document_size {
+				self.peeked = None;
+				self.truncated = true;
 			}
 		}
-		tokens.push(XmlToken::new_value(value));
-		next.and_then(|c| 
-			if c.is_quote() {
-				tokens.push(XmlToken::new_quote());
-				iter.next()
+	}
+	pub(crate) fn peek(&self) -> Option<char> {
+		self.peeked
+	}
+	fn next(&mut self) -> Option<char> {
+		let current = self.peeked;
+		if let Some(c) = current {
+			self.position.offset += self.skipped;
+			self.skipped = 0;
+			self.position.advance(c);
+			self.fill();
+		}
+		current
+	}
+	fn position(&self) -> XmlPosition {
+		self.position
+	}
+	/// Parses the next token like `parse_token`, checking the limits that
+	/// apply to the input as a whole.
+	pub(crate) fn parse_limited(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<bool> {
+		let before = tokens.len();
+		let result = self.parse_token(tokens);
+		if self.truncated {
+			return Err(ExtractError::DocumentTooLarge(self.limits.max_document_size, self.position));
+		}
+		self.count += tokens.len() - before;
+		if self.count > self.limits.max_tokens {
+			let start = tokens.get(before).map_or(self.position, |token| token.span.start);
+			return Err(ExtractError::TooManyTokens(self.limits.max_tokens, start));
+		}
+		result
+	}
+	/// Fails when the text has grown past the limit, `start` is the start of the token.
+	fn check_length<F: Fn(usize, XmlPosition) -> ExtractError>(&self, text: &XmlText, limit: usize, error: F, start: XmlPosition) -> ExtractResult<()> {
+		if self.text_str(text).len() > limit {
+			return Err(error(limit, start));
+		}
+		Ok(())
+	}
+	fn push(&self, token: XmlToken<'a>, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) {
+		tokens.push(XmlSpanned::new(token, XmlSpan::between(start, self.position)));
+	}
+	/// Starts text at the next character.
+	fn start_text(&self) -> XmlText {
+		XmlText {
+			start: self.position.offset,
+			owned: match self.source {
+				Some(_) => None,
+				None => Some(String::new()),
+			},
+		}
+	}
+	/// Switches the text to a copy, from here on it differs from the input.
+	fn own_text(&self, text: &mut XmlText) {
+		if text.owned.is_none() {
+			text.owned = Some(self.source.unwrap()[text.start..self.position.offset].to_string());
+		}
+	}
+	/// Consumes the next character as part of the text.
+	fn next_text(&mut self, text: &mut XmlText) {
+		if self.normalized {
+			self.own_text(text);
+		}
+		let c = self.next().unwrap();
+		if let Some(ref mut owned) = text.owned {
+			owned.push(c);
+		}
+	}
+	/// Reads a reference and adds its replacement to the text.
+	fn reference_text(&mut self, text: &mut XmlText) -> ExtractResult<()> {
+		self.own_text(text);
+		let replacement = self.read_reference()?;
+		text.owned.as_mut().unwrap().push_str(&replacement);
+		Ok(())
+	}
+	fn text_str<'t>(&'t self, text: &'t XmlText) -> &'t str {
+		match text.owned {
+			Some(ref owned) => owned,
+			None => &self.source.unwrap()[text.start..self.position.offset],
+		}
+	}
+	/// The text without its last `trim` bytes.
+	fn finish_text(&self, text: XmlText, trim: usize) -> Cow<'a, str> {
+		match text.owned {
+			Some(mut owned) => {
+				let length = owned.len() - trim;
+				owned.truncate(length);
+				Cow::Owned(owned)
+			},
+			None => Cow::Borrowed(&self.source.unwrap()[text.start..self.position.offset - trim]),
+		}
+	}
+	/// Consumes the given characters, failing at the first one that differs.
+	fn expect_str(&mut self, value: &str) -> ExtractResult<()> {
+		for expected in value.chars() {
+			match self.peek() {
+				Some(c) if c == expected => {
+					self.next();
+				},
+				Some(c) => return Err(ExtractError::InvalidMarkup(c, self.position())),
+				None => return Err(ExtractError::UnexpectedEnd(self.position())),
 			}
-			else {
-				panic!("missing quote for attribute value");
+		}
+		Ok(())
+	}
+	/// Consumes everything up to and including the terminator and returns the
+	/// text before it, `None` when the input ends first.
+	fn read_until(&mut self, terminator: &str) -> ExtractResult<Option<Cow<'a, str>>> {
+		let start = self.position();
+		let mut text = self.start_text();
+		while let Some(c) = self.peek() {
+			if !c.is_valid_in_xml() {
+				return Err(ExtractError::InvalidChar(c, self.position()));
+			}
+			self.next_text(&mut text);
+			if self.text_str(&text).ends_with(terminator) {
+				return Ok(Some(self.finish_text(text, terminator.len())));
+			}
+			// the text may still end in the start of the terminator
+			let limit = self.limits.max_text_length;
+			self.check_length(&text, limit.saturating_add(terminator.len() - 1), |_, start| ExtractError::TextTooLong(limit, start), start)?;
+		}
+		Ok(None)
+	}
+	/// Reads a `&name;` reference and returns its replacement text.
+	fn read_reference(&mut self) -> ExtractResult<String> {
+		let start = self.position();
+		self.next();
+		let mut reference = String::new();
+		loop {
+			match self.peek() {
+				Some(';') => {
+					self.next();
+					return self.entities.expand(&reference, start);
+				},
+				Some(c) if c == '#' || c.is_valid_char_in_element_name() => {
+					reference.push(c);
+					self.next();
+				},
+				_ => return Err(ExtractError::InvalidReference(reference, start)),
 			}
-		)
+		}
 	}
-	fn parse_name(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char> {
+	fn read_name(&mut self) -> ExtractResult<Cow<'a, str>> {
 		use tokenizer::XmlValidation;
 
-		let mut next: Option<char>;
-		if value.is_valid_first_char_in_element_name() {
-			let mut name: String = String::new();
-			name.push(*value);
-			loop {
-				next = iter.next();
-				if next.is_some() {
-					let c = next.unwrap();
-					if c.is_valid_char_in_element_name() {
-						name.push(c);
-					}
-					else {
-						break;
-					}
-				} 
-				else {
-					break;
+		let start = self.position();
+		let mut name = self.start_text();
+		match self.peek() {
+			Some(c) if c.is_valid_first_char_in_element_name() => self.next_text(&mut name),
+			Some(c) => return Err(ExtractError::InvalidNameChar(c, self.position())),
+			None => return Err(ExtractError::UnexpectedEnd(self.position())),
+		}
+		self.check_length(&name, self.limits.max_name_length, ExtractError::NameTooLong, start)?;
+		while let Some(c) = self.peek() {
+			if !c.is_valid_char_in_element_name() {
+				break;
+			}
+			self.next_text(&mut name);
+			self.check_length(&name, self.limits.max_name_length, ExtractError::NameTooLong, start)?;
+		}
+		Ok(self.finish_text(name, 0))
+	}
+}
+
+impl<'a, I: Iterator<Item = char>> XmlParse<'a> for XmlCursor<'a, I> {
+	/// Parses the token starting at the next character, returns false at the end of the input.
+	fn parse_token(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<bool> {
+		use token::XmlToken::*;
+		match self.peek() {
+			Some('<') => self.parse_markup(tokens)?,
+			Some(_) if !self.in_tag => self.parse_text(tokens)?,
+			Some(c) if c.is_whitespace_in_xml() => self.parse_whitespace(tokens)?,
+			Some(c) => {
+				match XmlToken::from_char(c) {
+					Some(Quote(_)) => self.parse_value(tokens)?,
+					Some(token) => self.parse_symbol(token, tokens),
+					None => self.parse_name(tokens)?,
 				}
-			} 
-			tokens.push(XmlToken::new_name(name));
+			},
+			None => return Ok(false),
 		}
-		else {
-			panic!("Invalid namespace character '{}'", value);
+		Ok(true)
+	}
+	fn parse_symbol(&mut self, token: XmlToken<'a>, tokens: &mut XmlTokenVec<'a>) {
+		let start = self.position();
+		self.next();
+		if token == XmlToken::End {
+			self.in_tag = false;
+		}
+		// an empty element tag closes its element right away
+		if token == XmlToken::Close && self.in_tag && !self.end_tag {
+			self.depth = self.depth.saturating_sub(1);
+			self.end_tag = true;
 		}
-		next
+		self.push(token, start, tokens);
 	}
-	fn parse_whitespace(value: &char, iter: &mut Chars, tokens: &mut XmlTokenVec) -> Option<char> {
-		// find all same as value
-		assert!(value.is_whitespace());
-		let mut next = iter.next();
-		let mut count = 1;
-		loop {
-			if next.is_some() {
-				let c = next.unwrap();
-				if &c == value {
-					count += 1;
-					next = iter.next();
+	/// Parses `<` and, when it starts one, the comment, CDATA section or processing instruction.
+	fn parse_markup(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let start = self.position();
+		self.next();
+		match self.peek() {
+			Some('!') => {
+				self.next();
+				match self.peek() {
+					Some('-') => self.parse_comment(start, tokens),
+					Some('[') => self.parse_cdata(start, tokens),
+					Some('D') => self.parse_doctype(start, tokens),
+					Some(c) => Err(ExtractError::InvalidMarkup(c, self.position())),
+					None => Err(ExtractError::UnexpectedEnd(self.position())),
+				}
+			},
+			Some('?') => {
+				self.next();
+				self.parse_processing_instruction(start, tokens)
+			},
+			_ => {
+				self.in_tag = true;
+				self.names = 0;
+				self.end_tag = self.peek() == Some('/');
+				if self.end_tag {
+					self.depth = self.depth.saturating_sub(1);
 				}
 				else {
-					break;
+					self.depth += 1;
+					if self.depth > self.limits.max_depth {
+						return Err(ExtractError::NestingTooDeep(self.limits.max_depth, start));
+					}
 				}
-			}
-			else {
-				break;
-			}
+				self.push(XmlToken::new_begin(), start, tokens);
+				Ok(())
+			},
 		}
-		tokens.push(XmlToken::Whitespace(WhitespaceKind::from_char(*value, count).unwrap()));
-		next
 	}
-}
-
-impl XmlTokenize for String {
-	fn tokenize(&self) -> XmlTokenVec {
-		use token::XmlToken::*;
-		let mut result = XmlTokenVec::new();
-		let mut iter = self.chars();
-		let mut next = iter.next();
+	fn parse_comment(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		self.expect_str("--")?;
+		let text = self.read_until("-->")?.ok_or(ExtractError::UnterminatedComment(start))?;
+		// "--" must not occur inside a comment
+		if text.contains("--") || text.ends_with('-') {
+			return Err(ExtractError::InvalidComment(start));
+		}
+		self.push(XmlToken::new_comment(text), start, tokens);
+		Ok(())
+	}
+	fn parse_cdata(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		self.expect_str("[CDATA[")?;
+		let text = self.read_until("]]>")?.ok_or(ExtractError::UnterminatedCData(start))?;
+		self.push(XmlToken::new_cdata(text), start, tokens);
+		Ok(())
+	}
+	fn parse_doctype(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		self.expect_str("DOCTYPE")?;
+		let position = self.position();
+		let mut text = self.start_text();
+		let mut quote = None;
+		let mut skip_until = None; // end of a comment or processing instruction in the internal subset
+		let mut depth = 0;
 		loop {
-			next = next.as_ref().and_then(|c| 
-				if c.is_whitespace() {
-					Self::parse_whitespace(c, &mut iter, &mut result)
+			let c = match self.peek() {
+				Some(c) if !c.is_valid_in_xml() => return Err(ExtractError::InvalidChar(c, self.position())),
+				Some(c) => c,
+				None => return Err(ExtractError::UnterminatedDoctype(start)),
+			};
+			self.next_text(&mut text);
+			if let Some(terminator) = skip_until {
+				if self.text_str(&text).ends_with(terminator) {
+					skip_until = None;
 				}
-				else {
-					let token = XmlToken::from_char(*c);
-					match token {
-						Some(Begin) |
-						Some(Close) |
-						Some(End) |
-						Some(Assign) => { 
-							result.push(token.unwrap());
-							iter.next()
-						},
-						Some(Quote) => {
-							result.push(token.unwrap());
-							Self::parse_value(c, &mut iter, &mut result)
-						},
-						_ => {
-							Self::parse_name(c, &mut iter, &mut result)
-						},
+			}
+			else {
+				match c {
+					_ if quote == Some(c) => quote = None,
+					_ if quote.is_some() => (),
+					'"' | '\'' => quote = Some(c),
+					'[' => depth += 1,
+					']' => depth -= 1,
+					'>' if depth <= 0 => break,
+					_ => (),
+				}
+				if depth > 0 && quote.is_none() {
+					let read = self.text_str(&text);
+					if read.ends_with("<!--") {
+						skip_until = Some("-->");
+					}
+					else if read.ends_with("<?") {
+						skip_until = Some("?>");
 					}
 				}
-			);
-			if next.is_none() {
+			}
+			self.check_length(&text, self.limits.max_text_length, ExtractError::TextTooLong, start)?;
+		}
+		let doctype = XmlDoctype::parse(&self.finish_text(text, 1), position)?;
+		self.entities.declare(&doctype);
+		self.push(XmlToken::new_doctype(doctype), start, tokens);
+		Ok(())
+	}
+	fn parse_processing_instruction(&mut self, start: XmlPosition, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let target = self.read_name()?;
+		match self.peek() {
+			Some(c) if c.is_whitespace_in_xml() => {
+				while self.peek().is_some_and(|c| c.is_whitespace_in_xml()) {
+					self.next();
+				}
+			},
+			Some('?') => (),
+			Some(c) => return Err(ExtractError::InvalidNameChar(c, self.position())),
+			None => return Err(ExtractError::UnterminatedProcessingInstruction(start)),
+		}
+		let data = self.read_until("?>")?.ok_or(ExtractError::UnterminatedProcessingInstruction(start))?;
+		// targets matching [Xx][Mm][Ll] are reserved, only the declaration may use one
+		if target.eq_ignore_ascii_case("xml") {
+			if target != "xml" {
+				return Err(ExtractError::InvalidDeclaration(target.into_owned(), start));
+			}
+			if start.offset != 0 {
+				return Err(ExtractError::MisplacedDeclaration(start));
+			}
+			let declaration = XmlDeclaration::parse(&data, start)?;
+			self.push(XmlToken::new_declaration(declaration), start, tokens);
+		}
+		else {
+			self.push(XmlToken::new_processing_instruction(target, data), start, tokens);
+		}
+		Ok(())
+	}
+	fn parse_value(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let start = self.position();
+		let quote = self.next().unwrap();
+		let kind = QuoteKind::from_char(quote).unwrap();
+		self.push(XmlToken::Quote(kind), start, tokens);
+		let value_start = self.position();
+		let mut text = self.start_text();
+		loop {
+			match self.peek() {
+				Some(c) if c == quote => break,
+				Some('&') => self.reference_text(&mut text)?,
+				Some(c) if !c.is_valid_in_xml() => return Err(ExtractError::InvalidChar(c, self.position())),
+				Some(_) => self.next_text(&mut text),
+				None => return Err(ExtractError::UnterminatedValue(quote, start)),
+			}
+			self.check_length(&text, self.limits.max_value_length, ExtractError::ValueTooLong, value_start)?;
+		}
+		let value = self.finish_text(text, 0);
+		self.push(XmlToken::new_value(value), value_start, tokens);
+		self.parse_symbol(XmlToken::Quote(kind), tokens);
+		Ok(())
+	}
+	/// Parses character data up to the next markup, runs consisting only of
+	/// whitespace become whitespace tokens.
+	fn parse_text(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let start = self.position();
+		let mut text = self.start_text();
+		let mut whitespace = true;
+		loop {
+			match self.peek() {
+				None | Some('<') => break,
+				Some('&') => {
+					self.reference_text(&mut text)?;
+					whitespace = false;
+				},
+				Some(c) if !c.is_valid_in_xml() => return Err(ExtractError::InvalidChar(c, self.position())),
+				Some(c) => {
+					whitespace = whitespace && c.is_whitespace_in_xml();
+					self.next_text(&mut text);
+				},
+			}
+			self.check_length(&text, self.limits.max_text_length, ExtractError::TextTooLong, start)?;
+		}
+		let text = self.finish_text(text, 0);
+		let token = if whitespace {
+			XmlToken::new_whitespace(text.into_owned()).unwrap()
+		}
+		else {
+			XmlToken::new_text(text)
+		};
+		self.push(token, start, tokens);
+		Ok(())
+	}
+	fn parse_name(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let start = self.position();
+		// the first name of a start tag is the element, all others are attributes
+		if !self.end_tag {
+			self.names += 1;
+			if self.names - 1 > self.limits.max_attributes {
+				return Err(ExtractError::TooManyAttributes(self.limits.max_attributes, start));
+			}
+		}
+		let name = self.read_name()?;
+		self.push(XmlToken::new_name(name), start, tokens);
+		Ok(())
+	}
+	fn parse_whitespace(&mut self, tokens: &mut XmlTokenVec<'a>) -> ExtractResult<()> {
+		let start = self.position();
+		let mut value = String::new();
+		while let Some(c) = self.peek() {
+			if !c.is_whitespace_in_xml() {
 				break;
 			}
+			value.push(c);
+			self.next();
+			if value.len() > self.limits.max_text_length {
+				return Err(ExtractError::TextTooLong(self.limits.max_text_length, start));
+			}
+		}
+		self.push(XmlToken::new_whitespace(value).unwrap(), start, tokens);
+		Ok(())
+	}
+}
+
+impl<'a> XmlTokenize<'a> for &'a str {
+	fn tokenize_with(self, options: &XmlOptions) -> ExtractResult<XmlTokenVec<'a>> {
+		XmlTokenizer::new_borrowed(self, options).collect()
+	}
+}
+
+impl<'a> XmlTokenize<'a> for &'a String {
+	fn tokenize_with(self, options: &XmlOptions) -> ExtractResult<XmlTokenVec<'a>> {
+		self.as_str().tokenize_with(options)
+	}
+}
+
+impl<'a> XmlTokenize<'a> for String {
+	fn tokenize_with(self, options: &XmlOptions) -> ExtractResult<XmlTokenVec<'a>> {
+		let tokens = self.as_str().tokenize_with(options)?;
+		Ok(tokens.into_iter().map(XmlSpanned::into_owned).collect())
+	}
+}
+
+/// Raw document bytes, decoded according to the byte order mark and the
+/// encoding in the XML declaration. UTF-8 input is borrowed, anything else
+/// is transcoded first and gives owned tokens.
+impl<'a> XmlTokenize<'a> for &'a [u8] {
+	fn tokenize_with(self, options: &XmlOptions) -> ExtractResult<XmlTokenVec<'a>> {
+		match encoding::decode(self)? {
+			Cow::Borrowed(text) => text.tokenize_with(options),
+			Cow::Owned(text) => text.tokenize_with(options),
 		}
-		result
 	}
 }
 
@@ -229,8 +669,21 @@ pub fn new_whitespace_kind(value: &char, count: usize) -> Option<WhitespaceKind>
 #[cfg(test)]
 mod tests {
 	use token::WhitespaceKind;
+	use token::XmlWhitespace;
 	use token::XmlToken;
+	use declaration::XmlDeclaration;
+	use doctype::XmlExternalId;
+	use errors::ExtractError;
+	use span::XmlPosition;
+	use span::XmlSpan;
+	use span::XmlSpanned;
+	use entity::XmlEntityLimit;
+	use options::XmlEntityLimits;
+	use options::XmlLimits;
+	use options::XmlLineEndings;
+	use options::XmlOptions;
 	use super::XmlTokenize;
+	use super::XmlValidation;
 
 
 	#[test]
@@ -240,30 +693,30 @@ mod tests {
 	}
 	#[test]
 	fn new_tokenizer() {
-		let tokenizer = "".to_string().tokenize();
+		let tokenizer = "".to_string().tokenize().unwrap();
 		assert_eq!(tokenizer.len(), 0);
 	}
 	#[test]
 	fn new_tokenizer_one_space() {
-		let tokenizer = " ".to_string().tokenize();
+		let tokenizer = " ".to_string().tokenize().unwrap();
 		{
 			let mut iter = tokenizer.iter();
-			assert_eq!(iter.next().unwrap(), &XmlToken::Whitespace(WhitespaceKind::Space(1)));
+			assert_eq!(iter.next().unwrap().token, XmlToken::Whitespace(XmlWhitespace::from(WhitespaceKind::Space(1))));
 		}
 	}
 	#[test]
 	fn new_tokenizer_xml_element() {
-		let tokenizer = "<element>".to_string().tokenize();
+		let tokenizer = "<element>".to_string().tokenize().unwrap();
 		{
 			let mut iter = tokenizer.iter();
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("element".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("element".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
 		}
 	}
 	#[test]
 	fn new_tokenizer_xml_element_to_string() {
-		let tokenizer = "<element>".to_string().tokenize();
+		let tokenizer = "<element>".to_string().tokenize().unwrap();
 		let mut text = String::new();
 		for token in tokenizer {
 			text = text + &token.to_string();
@@ -272,54 +725,54 @@ mod tests {
 	}
 	#[test]
 	fn new_tokenizer_xml_full_element() {
-		let tokenizer = "<element/>".to_string().tokenize();
+		let tokenizer = "<element/>".to_string().tokenize().unwrap();
 		{
 			let mut iter = tokenizer.iter();
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("element".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_close());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("element".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_close());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
 		}
 	}
 	#[test]
 	fn new_tokenizer_xml_element_in_element() {
-		let tokenizer = "<element><level/></element>".to_string().tokenize();
+		let tokenizer = "<element><level/></element>".to_string().tokenize().unwrap();
 		{
 			let mut iter = tokenizer.iter();
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("element".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("level".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_close());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_close());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("element".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("element".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("level".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_close());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_close());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("element".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
 		}
 	}
 	#[test]
 	fn new_tokenizer_xml_full_element_with_attribute() {
-		let tokenizer = "<element attribute=\"value\"/>".to_string().tokenize();
+		let tokenizer = "<element attribute=\"value\"/>".to_string().tokenize().unwrap();
 		assert_eq!(tokenizer.len(), 10);
 		{
 			let mut iter = tokenizer.iter();
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_begin());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("element".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::Whitespace(WhitespaceKind::Space(1)));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_name("attribute".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_assign());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_quote());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_value("value".to_string()));
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_quote());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_close());
-			assert_eq!(iter.next().unwrap(), &XmlToken::new_end());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_begin());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("element".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::Whitespace(XmlWhitespace::from(WhitespaceKind::Space(1))));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_name("attribute".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_assign());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_quote());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_value("value".to_string()));
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_quote());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_close());
+			assert_eq!(iter.next().unwrap().token, XmlToken::new_end());
 		}
 	}
 	#[test]
 	fn new_tokenizer_xml_full_element_with_attribute_to_string() {
-		let tokenizer = "<element attribute=\"value\"/>".to_string().tokenize();
+		let tokenizer = "<element attribute=\"value\"/>".to_string().tokenize().unwrap();
 		assert_eq!(tokenizer.len(), 10);
 		let mut text = String::new();
 		for token in tokenizer {
@@ -327,4 +780,327 @@ mod tests {
 		}
 		assert_eq!(text, "<element attribute=\"value\"/>".to_string());
 	}
+	#[test]
+	fn new_tokenizer_unterminated_value() {
+		match "<element attribute=\"value/>".to_string().tokenize() {
+			Err(ExtractError::UnterminatedValue(c, position)) => {
+				assert_eq!(c, '\"');
+				assert_eq!(position, XmlPosition::new(19, 1, 20));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn new_tokenizer_invalid_name_char() {
+		match "<element attribute=\"value\" 1st=\"\"/>".to_string().tokenize() {
+			Err(ExtractError::InvalidNameChar(c, position)) => {
+				assert_eq!(c, '1');
+				assert_eq!(position, XmlPosition::new(27, 1, 28));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn new_tokenizer_spans() {
+		let tokenizer = "<a>\n  <b x=\"1\"/>".to_string().tokenize().unwrap();
+		let spans: Vec<XmlSpan> = tokenizer.iter().map(|token| token.span).collect();
+		assert_eq!(spans[1], XmlSpan::between(XmlPosition::new(1, 1, 2), XmlPosition::new(2, 1, 3)));
+		assert_eq!(spans[3], XmlSpan::between(XmlPosition::new(3, 1, 4), XmlPosition::new(6, 2, 3)));
+		assert_eq!(spans[4], XmlSpan::between(XmlPosition::new(6, 2, 3), XmlPosition::new(7, 2, 4)));
+		assert_eq!(spans[5], XmlSpan::between(XmlPosition::new(7, 2, 4), XmlPosition::new(8, 2, 5)));
+		assert_eq!(spans[10], XmlSpan::between(XmlPosition::new(12, 2, 9), XmlPosition::new(13, 2, 10)));
+		assert_eq!(spans[12], XmlSpan::between(XmlPosition::new(14, 2, 11), XmlPosition::new(15, 2, 12)));
+	}
+	#[test]
+	fn new_tokenizer_error_display() {
+		let error = "<a>\n<b c=\"d/>".to_string().tokenize().unwrap_err();
+		assert_eq!(format!("{}", error), "Unterminated attribute value starting with \" at 2:6".to_string());
+	}
+	#[test]
+	fn new_tokenizer_comment() {
+		let tokenizer = "<a><!-- <b/> - c --></a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer.len(), 8);
+		assert_eq!(tokenizer[3].token, XmlToken::new_comment(" <b/> - c ".to_string()));
+		assert_eq!(tokenizer[3].span, XmlSpan::between(XmlPosition::new(3, 1, 4), XmlPosition::new(20, 1, 21)));
+		assert_eq!(tokenizer[3].to_string(), "<!-- <b/> - c -->".to_string());
+	}
+	#[test]
+	fn new_tokenizer_cdata() {
+		let tokenizer = "<a><![CDATA[<b> & ]] ]]></a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_cdata("<b> & ]] ".to_string()));
+		assert_eq!(tokenizer[3].to_string(), "<![CDATA[<b> & ]] ]]>".to_string());
+	}
+	#[test]
+	fn new_tokenizer_processing_instruction() {
+		let tokenizer = "<?stylesheet type=\"text/xsl\" href=\"a.xsl\"?><?empty?>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer.len(), 2);
+		assert_eq!(tokenizer[0].token, XmlToken::new_processing_instruction("stylesheet".to_string(), "type=\"text/xsl\" href=\"a.xsl\"".to_string()));
+		assert_eq!(tokenizer[1].token, XmlToken::new_processing_instruction("empty".to_string(), "".to_string()));
+		assert_eq!(tokenizer[1].to_string(), "<?empty?>".to_string());
+	}
+	#[test]
+	fn new_tokenizer_markup_errors() {
+		assert!(matches!("<!-- a".to_string().tokenize(), Err(ExtractError::UnterminatedComment(_))));
+		assert!(matches!("<!-- a -- b -->".to_string().tokenize(), Err(ExtractError::InvalidComment(_))));
+		assert!(matches!("<![CDATA[ a ]>".to_string().tokenize(), Err(ExtractError::UnterminatedCData(_))));
+		assert!(matches!("<![CDTA[ a ]]>".to_string().tokenize(), Err(ExtractError::InvalidMarkup('T', _))));
+		assert!(matches!("<?pi data".to_string().tokenize(), Err(ExtractError::UnterminatedProcessingInstruction(_))));
+		assert!(matches!("<!x>".to_string().tokenize(), Err(ExtractError::InvalidMarkup('x', _))));
+	}
+	#[test]
+	fn new_tokenizer_declaration() {
+		let tokenizer = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<a/>".to_string().tokenize().unwrap();
+		let declaration = XmlDeclaration::new("1.0".to_string(), Some("UTF-8".to_string()), Some(true));
+		assert_eq!(tokenizer[0].token, XmlToken::new_declaration(declaration));
+		assert_eq!(tokenizer[0].span.length(), 55);
+		assert_eq!(tokenizer[0].to_string(), "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>".to_string());
+	}
+	#[test]
+	fn new_tokenizer_declaration_errors() {
+		assert!(matches!(" <?xml version=\"1.0\"?>".to_string().tokenize(), Err(ExtractError::MisplacedDeclaration(_))));
+		assert!(matches!("<a/><?xml version=\"1.0\"?>".to_string().tokenize(), Err(ExtractError::MisplacedDeclaration(_))));
+		assert!(matches!("<?XML version=\"1.0\"?>".to_string().tokenize(), Err(ExtractError::InvalidDeclaration(_, _))));
+		assert!(matches!("<?xml encoding=\"UTF-8\"?>".to_string().tokenize(), Err(ExtractError::InvalidDeclaration(_, _))));
+	}
+	#[test]
+	fn new_tokenizer_doctype() {
+		let text = "<!DOCTYPE a PUBLIC \"-//A//EN\" 'a.dtd' [\n\t<!-- ]> -->\n\t<!ENTITY gt2 '>>'>\n]>\n<a/>";
+		let tokenizer = text.tokenize().unwrap();
+		let doctype = match tokenizer[0].token {
+			XmlToken::Doctype(ref doctype) => doctype,
+			ref other => panic!("unexpected token {:?}", other),
+		};
+		assert_eq!(doctype.external_id, Some(XmlExternalId::Public("-//A//EN".to_string(), Some("a.dtd".to_string()))));
+		assert_eq!(doctype.declarations.len(), 1);
+		assert_eq!(tokenizer[0].span.length(), 75);
+		assert_eq!(tokenizer[1].token, XmlToken::new_whitespace("\n".to_string()).unwrap());
+		assert_eq!(tokenizer[0].to_string(), "<!DOCTYPE a PUBLIC \"-//A//EN\" \"a.dtd\" [<!ENTITY gt2 \">>\">]>".to_string());
+	}
+	#[test]
+	fn new_tokenizer_entity_expansion() {
+		let text = "<!DOCTYPE a [<!ENTITY company 'Acme &amp; Sons'><!ENTITY signed '&company; Corp'>]><a by=\"&signed;\">&signed;, &lt;3</a>";
+		let tokenizer = text.tokenize().unwrap();
+		assert_eq!(tokenizer[7].token, XmlToken::new_value("Acme & Sons Corp"));
+		assert_eq!(tokenizer[10].token, XmlToken::new_text("Acme & Sons Corp, <3"));
+		assert!(matches!("<!DOCTYPE a [<!ENTITY e SYSTEM 'e.xml'>]><a>&e;</a>".tokenize(), Err(ExtractError::UnknownEntity(_, _))));
+		assert!(matches!("<a>&company;</a>".tokenize(), Err(ExtractError::UnknownEntity(_, _))));
+	}
+	#[test]
+	fn new_tokenizer_entity_limits() {
+		let text = "<!DOCTYPE a [<!ENTITY a 'aaaa'><!ENTITY b '&a;&a;&a;&a;'>]><a>&b;&b;</a>";
+		assert!(text.tokenize().is_ok());
+		let limits = XmlEntityLimits { max_size: 24, ..XmlEntityLimits::default() };
+		match text.tokenize_with(&XmlOptions::new().with_entity_limits(limits)) {
+			Err(ExtractError::EntityLimitExceeded(limit, position)) => {
+				assert_eq!(limit, XmlEntityLimit::Size);
+				assert_eq!(position.offset, 65);
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn new_tokenizer_limits() {
+		let text = "<a x='1' y='22'><b><c/></b><b/><!--333-->4444</a>";
+		let limited = |limits: XmlLimits| text.tokenize_with(&XmlOptions::new().with_limits(limits));
+		assert!(limited(XmlLimits { max_depth: 3, max_attributes: 2, max_name_length: 1, max_value_length: 2, max_text_length: 4, max_tokens: 36, max_document_size: 49 }).is_ok());
+		let error = |limits: XmlLimits| limited(limits).unwrap_err();
+		assert!(matches!(error(XmlLimits { max_depth: 2, ..XmlLimits::default() }), ExtractError::NestingTooDeep(2, XmlPosition { offset: 19, .. })));
+		assert!(matches!(error(XmlLimits { max_attributes: 1, ..XmlLimits::default() }), ExtractError::TooManyAttributes(1, XmlPosition { offset: 9, .. })));
+		assert!(matches!(error(XmlLimits { max_name_length: 0, ..XmlLimits::default() }), ExtractError::NameTooLong(0, XmlPosition { offset: 1, .. })));
+		assert!(matches!(error(XmlLimits { max_value_length: 1, ..XmlLimits::default() }), ExtractError::ValueTooLong(1, XmlPosition { offset: 12, .. })));
+		assert!(matches!(error(XmlLimits { max_text_length: 3, ..XmlLimits::default() }), ExtractError::TextTooLong(3, XmlPosition { offset: 41, .. })));
+		assert!(matches!(error(XmlLimits { max_text_length: 2, ..XmlLimits::default() }), ExtractError::TextTooLong(2, XmlPosition { offset: 35, .. })));
+		assert!(matches!(error(XmlLimits { max_tokens: 35, ..XmlLimits::default() }), ExtractError::TooManyTokens(35, XmlPosition { offset: 48, .. })));
+		assert!(matches!(error(XmlLimits { max_document_size: 48, ..XmlLimits::default() }), ExtractError::DocumentTooLarge(48, XmlPosition { offset: 48, .. })));
+		let spaces = format!("<a{}/>", " ".repeat(5));
+		let limits = XmlLimits { max_text_length: 4, ..XmlLimits::default() };
+		assert!(matches!(spaces.tokenize_with(&XmlOptions::new().with_limits(limits)), Err(ExtractError::TextTooLong(4, XmlPosition { offset: 2, .. }))));
+		// " a [<!ELEMENT a ANY>]" after the keyword
+		let doctype = |max_text_length: usize| "<!DOCTYPE a [<!ELEMENT a ANY>]><a/>".tokenize_with(&XmlOptions::new().with_limits(XmlLimits { max_text_length, ..XmlLimits::default() }));
+		assert!(doctype(21).is_ok());
+		assert!(matches!(doctype(20), Err(ExtractError::TextTooLong(20, XmlPosition { offset: 0, .. }))));
+	}
+	#[test]
+	fn new_tokenizer_doctype_errors() {
+		assert!(matches!("<!DOCTYPE a [<!ELEMENT a ANY>".tokenize(), Err(ExtractError::UnterminatedDoctype(_))));
+		assert!(matches!("<!DOCTYPE a SYSTEM 'a.dtd>".tokenize(), Err(ExtractError::UnterminatedDoctype(_))));
+		assert!(matches!("<!DOCTYP a>".tokenize(), Err(ExtractError::InvalidMarkup(' ', _))));
+		match "<!DOCTYPE a [\n<!ELEMENT a EMPTIED>]>".tokenize() {
+			Err(ExtractError::InvalidDoctype(text, position)) => {
+				assert_eq!(text, "EMPTIED");
+				assert_eq!(position, XmlPosition::new(26, 2, 13));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn new_tokenizer_single_quoted_value() {
+		let tokenizer = "<a b='say \"hi\"' c=\"it's\"/>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer.len(), 16);
+		assert_eq!(tokenizer[5].token, XmlToken::new_single_quote());
+		assert_eq!(tokenizer[6].token, XmlToken::new_value("say \"hi\"".to_string()));
+		assert_eq!(tokenizer[7].token, XmlToken::new_single_quote());
+		assert_eq!(tokenizer[11].token, XmlToken::new_quote());
+		assert_eq!(tokenizer[12].token, XmlToken::new_value("it's".to_string()));
+		assert_eq!(tokenizer[13].token, XmlToken::new_quote());
+		let text: String = tokenizer.iter().map(|token| token.to_string()).collect();
+		assert_eq!(text, "<a b='say &quot;hi&quot;' c=\"it&apos;s\"/>".to_string());
+	}
+	#[test]
+	fn new_tokenizer_unterminated_single_quoted_value() {
+		assert!(matches!("<a b='c\"/>".to_string().tokenize(), Err(ExtractError::UnterminatedValue('\'', _))));
+	}
+	#[test]
+	fn new_tokenizer_references() {
+		let tokenizer = "<a b=\"x &lt;&#x20AC;&#38; y\">&amp;</a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[6].token, XmlToken::new_value("x <€& y".to_string()));
+		assert_eq!(tokenizer[9].token, XmlToken::new_text("&".to_string()));
+		assert_eq!(tokenizer[9].span, XmlSpan::between(XmlPosition::new(29, 1, 30), XmlPosition::new(34, 1, 35)));
+	}
+	#[test]
+	fn new_tokenizer_reference_errors() {
+		match "<a b=\"&nbsp;\"/>".to_string().tokenize() {
+			Err(ExtractError::UnknownEntity(name, position)) => {
+				assert_eq!(name, "nbsp");
+				assert_eq!(position, XmlPosition::new(6, 1, 7));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+		assert!(matches!("<a>&#xFFFE;</a>".to_string().tokenize(), Err(ExtractError::InvalidReference(_, _))));
+		assert!(matches!("<a>& b</a>".to_string().tokenize(), Err(ExtractError::InvalidReference(_, _))));
+	}
+	#[test]
+	fn new_tokenizer_text() {
+		let tokenizer = "<p>Hello, world! a=\"b\" 1/2 > 0</p>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer.len(), 8);
+		assert_eq!(tokenizer[3].token, XmlToken::new_text("Hello, world! a=\"b\" 1/2 > 0".to_string()));
+		assert_eq!(tokenizer[3].span, XmlSpan::between(XmlPosition::new(3, 1, 4), XmlPosition::new(30, 1, 31)));
+		assert_eq!(tokenizer[4].token, XmlToken::new_begin());
+	}
+	#[test]
+	fn new_tokenizer_text_with_references() {
+		let tokenizer = "<p>fish &amp; chips</p>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_text("fish & chips".to_string()));
+		assert_eq!(tokenizer[3].span.length(), 16);
+	}
+	#[test]
+	fn new_tokenizer_whitespace_between_tags() {
+		let tokenizer = "<a>\n\t\t<b/> </a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_whitespace("\n\t\t".to_string()).unwrap());
+		assert_eq!(tokenizer[3].span, XmlSpan::between(XmlPosition::new(3, 1, 4), XmlPosition::new(6, 2, 3)));
+		assert_eq!(tokenizer[8].token, XmlToken::Whitespace(XmlWhitespace::from(WhitespaceKind::Space(1))));
+	}
+	#[test]
+	fn new_tokenizer_non_xml_whitespace_is_text() {
+		let tokenizer = "<a>\u{00A0}</a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_text("\u{00A0}".to_string()));
+	}
+	#[test]
+	fn name_characters() {
+		for name in &["xsl-template", "v1.2", ":root", "größe", "名前", "a\u{00B7}b", "x\u{0301}"] {
+			let mut chars = name.chars();
+			assert!(chars.next().unwrap().is_valid_first_char_in_element_name(), "{}", name);
+			assert!(chars.all(|c| c.is_valid_char_in_element_name()), "{}", name);
+		}
+		for c in &['-', '.', '1', '\u{00B7}', '\u{0300}', '\u{203F}'] {
+			assert!(!c.is_valid_first_char_in_element_name(), "{}", c);
+			assert!(c.is_valid_char_in_element_name(), "{}", c);
+		}
+		for c in &[' ', '<', '\u{00D7}', '\u{00F7}', '\u{037E}', '\u{2000}', '\u{3000}', '\u{FFFE}'] {
+			assert!(!c.is_valid_char_in_element_name(), "{}", c);
+		}
+	}
+	#[test]
+	fn new_tokenizer_unicode_names() {
+		let tokenizer = "<xsl-template v1.2=\"\"><größe/></xsl-template>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[1].token, XmlToken::new_name("xsl-template".to_string()));
+		assert_eq!(tokenizer[3].token, XmlToken::new_name("v1.2".to_string()));
+		assert_eq!(tokenizer[10].token, XmlToken::new_name("größe".to_string()));
+	}
+	#[test]
+	fn new_tokenizer_invalid_char() {
+		let invalid = |text: &str| match text.to_string().tokenize() {
+			Err(ExtractError::InvalidChar(c, position)) => (c, position.offset),
+			other => panic!("unexpected result {:?}", other),
+		};
+		assert_eq!(invalid("<a>x\u{0001}</a>"), ('\u{0001}', 4));
+		assert_eq!(invalid("<a b=\"\u{FFFF}\"/>"), ('\u{FFFF}', 6));
+		assert_eq!(invalid("<!-- \u{000B} -->"), ('\u{000B}', 5));
+	}
+	#[test]
+	fn new_tokenizer_skips_byte_order_mark() {
+		let tokenizer = "\u{FEFF}<?xml version=\"1.0\"?><a/>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[0].token, XmlToken::new_declaration(XmlDeclaration::new("1.0".to_string(), None, None)));
+		assert_eq!(tokenizer[0].span.start, XmlPosition::start());
+	}
+	#[test]
+	fn new_tokenizer_bytes() {
+		let mut bytes = vec![0xFF, 0xFE];
+		bytes.extend("<a b='€'/>".encode_utf16().flat_map(|unit| unit.to_le_bytes().to_vec()));
+		assert_eq!(bytes.tokenize().unwrap(), "<a b='€'/>".to_string().tokenize().unwrap());
+		let latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>gr\xF6\xDFe</a>";
+		assert_eq!(latin1.tokenize().unwrap()[4].token, XmlToken::new_text("größe".to_string()));
+		assert!(matches!(b"\xEF\xBB\xBF<?xml version=\"1.0\" encoding=\"UTF-16\"?>".tokenize(), Err(ExtractError::EncodingMismatch(_, _, _))));
+	}
+	#[test]
+	fn new_tokenizer_preserves_line_endings() {
+		let tokenizer = "<a>\r\n</a>".to_string().tokenize().unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_whitespace("\r\n".to_string()).unwrap());
+	}
+	#[test]
+	fn new_tokenizer_normalizes_line_endings() {
+		let options = XmlOptions::new().with_line_endings(XmlLineEndings::Normalize);
+		let windows = "<a>\r\n\t<b c=\"1\r\n2\"/>\r\n</a>".to_string().tokenize_with(&options).unwrap();
+		let unix = "<a>\n\t<b c=\"1\n2\"/>\n</a>".to_string().tokenize_with(&options).unwrap();
+		let tokens = |tokens: &Vec<_>| tokens.iter().map(|token: &XmlSpanned<XmlToken>| token.token.clone()).collect::<Vec<_>>();
+		assert_eq!(tokens(&windows), tokens(&unix));
+		assert_eq!(windows[3].token, XmlToken::new_whitespace("\n\t".to_string()).unwrap());
+		assert_eq!(windows[3].span, XmlSpan::between(XmlPosition::new(3, 1, 4), XmlPosition::new(6, 2, 2)));
+		assert_eq!(windows[4].span.start, XmlPosition::new(6, 2, 2));
+		assert_eq!(windows[15].span.start, XmlPosition::new(21, 4, 1));
+	}
+	#[test]
+	fn new_tokenizer_normalizes_lone_carriage_returns() {
+		let options = XmlOptions::new().with_line_endings(XmlLineEndings::Normalize);
+		let tokenizer = "<a>x\ry\r\r\n</a>".to_string().tokenize_with(&options).unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_text("x\ny\n\n".to_string()));
+		assert_eq!(tokenizer[4].span.start, XmlPosition::new(9, 4, 1));
+	}
+	#[test]
+	fn new_tokenizer_borrows_from_str() {
+		let text = "<a b=\"value\" c='x &amp; y'>text<!-- note --><?pi data?></a>";
+		let tokenizer = text.tokenize().unwrap();
+		assert_eq!(tokenizer[1].token, XmlToken::new_name("a"));
+		assert!(tokenizer[1].token.is_borrowed());
+		assert_eq!(tokenizer[6].token, XmlToken::new_value("value"));
+		assert!(tokenizer[6].token.is_borrowed());
+		assert_eq!(tokenizer[12].token, XmlToken::new_value("x & y"));
+		assert!(!tokenizer[12].token.is_borrowed());
+		assert!(tokenizer[15].token.is_borrowed());
+		assert_eq!(tokenizer[16].token, XmlToken::new_comment(" note "));
+		assert!(tokenizer[16].token.is_borrowed());
+		assert!(tokenizer[17].token.is_borrowed());
+		assert!(!"<a>x</a>".to_string().tokenize().unwrap()[3].token.is_borrowed());
+	}
+	#[test]
+	fn new_tokenizer_borrows_after_byte_order_mark() {
+		let tokenizer = "\u{FEFF}<name>".tokenize().unwrap();
+		assert_eq!(tokenizer[1].token, XmlToken::new_name("name"));
+		assert!(tokenizer[1].token.is_borrowed());
+	}
+	#[test]
+	fn new_tokenizer_copies_normalized_text() {
+		let options = XmlOptions::new().with_line_endings(XmlLineEndings::Normalize);
+		let tokenizer = "<a>x\r\ny</a>".tokenize_with(&options).unwrap();
+		assert_eq!(tokenizer[3].token, XmlToken::new_text("x\ny"));
+		assert!(!tokenizer[3].token.is_borrowed());
+	}
+	#[test]
+	fn new_tokenizer_borrows_utf8_bytes() {
+		let tokenizer = b"<a>text</a>".tokenize().unwrap();
+		assert!(tokenizer[3].token.is_borrowed());
+		let tokenizer = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>text</a>".tokenize().unwrap();
+		assert_eq!(tokenizer[4].token, XmlToken::new_text("text"));
+		assert!(!tokenizer[4].token.is_borrowed());
+	}
 }
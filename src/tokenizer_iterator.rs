@@ -1,36 +1,132 @@
-	use std::str::Chars;
+use std::collections::VecDeque;
+use std::str::Chars;
+use errors::ExtractResult;
+use options::XmlOptions;
+use span::XmlSpanned;
+use token::XmlToken;
+use tokenizer::XmlCursor;
+use tokenizer::XmlTokenVec;
 
-	trait Extractor {
-		fn peek_first(&mut self) -> Option<char>;
+
+/// Pull based tokenizer, each call to `next` parses only as much input as
+/// needed for the next token.
+///
+/// Tokens borrow from the input only when it was given as a whole with
+/// `new_borrowed`, otherwise their text is owned.
+pub struct XmlTokenizer<'a, I> {
+	cursor: XmlCursor<'a, I>,
+	tokens: VecDeque<XmlSpanned<XmlToken<'a>>>, // parsed tokens not yet returned
+	buffer: XmlTokenVec<'a>,
+	done: bool,
+}
+
+
+impl<'a> XmlTokenizer<'a, Chars<'a>> {
+	pub fn new_borrowed(text: &'a str, options: &XmlOptions) -> XmlTokenizer<'a, Chars<'a>> {
+		XmlTokenizer::with_cursor(XmlCursor::new(text.chars(), Some(text), options))
 	}
+}
 
 
-	impl <'a> Extractor for Chars<'a> {
-		fn peek_first(&mut self) -> Option<char> {
-			self.peekable().peek().cloned()
+impl<'a, I: Iterator<Item = char>> XmlTokenizer<'a, I> {
+	pub fn new(iter: I) -> XmlTokenizer<'a, I> {
+		XmlTokenizer::with_options(iter, &XmlOptions::default())
+	}
+	pub fn with_options(iter: I, options: &XmlOptions) -> XmlTokenizer<'a, I> {
+		XmlTokenizer::with_cursor(XmlCursor::new(iter, None, options))
+	}
+	fn with_cursor(cursor: XmlCursor<'a, I>) -> XmlTokenizer<'a, I> {
+		XmlTokenizer {
+			cursor,
+			tokens: VecDeque::new(),
+			buffer: XmlTokenVec::new(),
+			done: false,
 		}
 	}
+	/// Next character of the input without consuming it.
+	pub fn peek_char(&self) -> Option<char> {
+		self.cursor.peek()
+	}
+	pub fn get_ref(&self) -> &I {
+		&self.cursor.iter
+	}
+	pub fn get_mut(&mut self) -> &mut I {
+		&mut self.cursor.iter
+	}
+}
 
 
-	#[cfg(test)]
-	mod tests {
-		use std::str::Chars;
-		use super::Extractor;
+impl<'a, I: Iterator<Item = char>> Iterator for XmlTokenizer<'a, I> {
+	type Item = ExtractResult<XmlSpanned<XmlToken<'a>>>;
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if let Some(token) = self.tokens.pop_front() {
+				return Some(Ok(token));
+			}
+			if self.done {
+				return None;
+			}
+			match self.cursor.parse_limited(&mut self.buffer) {
+				Ok(true) => self.tokens.extend(self.buffer.drain(..)),
+				Ok(false) => self.done = true,
+				Err(error) => {
+					self.done = true;
+					self.buffer.clear();
+					return Some(Err(error));
+				},
+			}
+		}
+	}
+}
 
-		#[test]
-		fn peek_first_test() {
-			let mut iterator: Chars;
-			iterator = "".chars();
-			assert_eq!(iterator.peek_first(), None);
-			assert_eq!(iterator.as_str(), "".to_string());
 
-			iterator = "A".chars();
-			assert_eq!(iterator.peek_first(), Some('A'));
-			assert_eq!(iterator.as_str(), "".to_string());
+#[cfg(test)]
+mod tests {
+	use errors::ExtractError;
+	use token::WhitespaceKind;
+	use token::XmlWhitespace;
+	use token::XmlToken;
+	use super::XmlTokenizer;
 
-			iterator = "AB".chars();
-			assert_eq!(iterator.peek_first(), Some('A'));
-			assert_eq!(iterator.as_str(), "B".to_string());
-		}
-	} 
+	#[test]
+	fn peek_char_test() {
+		let mut tokenizer = XmlTokenizer::new("".chars());
+		assert_eq!(tokenizer.peek_char(), None);
+		assert_eq!(tokenizer.get_ref().as_str(), "");
+
+		tokenizer = XmlTokenizer::new("A".chars());
+		assert_eq!(tokenizer.peek_char(), Some('A'));
+		assert_eq!(tokenizer.peek_char(), Some('A'));
 
+		tokenizer = XmlTokenizer::new("AB".chars());
+		assert_eq!(tokenizer.peek_char(), Some('A'));
+		assert_eq!(tokenizer.get_ref().as_str(), "B");
+	}
+	#[test]
+	fn lazy_tokens() {
+		let mut tokenizer = XmlTokenizer::new("<a> <b".chars());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_begin());
+		assert_eq!(tokenizer.peek_char(), Some('a'));
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_name("a".to_string()));
+		assert_eq!(tokenizer.peek_char(), Some('>'));
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::new_end());
+		assert_eq!(tokenizer.next().unwrap().unwrap().token, XmlToken::Whitespace(XmlWhitespace::from(WhitespaceKind::Space(1))));
+		assert_eq!(tokenizer.get_ref().as_str(), "b");
+	}
+	#[test]
+	fn stop_early() {
+		// the unterminated value is never reached
+		let names: Vec<XmlToken> = XmlTokenizer::new("<a/><b c=\"".chars())
+			.map(|token| token.unwrap().token)
+			.take_while(|token| *token != XmlToken::new_end())
+			.collect();
+		assert_eq!(names, vec![XmlToken::new_begin(), XmlToken::new_name("a".to_string()), XmlToken::new_close()]);
+	}
+	#[test]
+	fn error_ends_iteration() {
+		let mut tokenizer = XmlTokenizer::new("<a b=\"c".chars());
+		assert!(tokenizer.by_ref().take(5).all(|token| token.is_ok()));
+		assert!(matches!(tokenizer.next(), Some(Err(ExtractError::UnterminatedValue('"', _)))));
+		assert!(tokenizer.next().is_none());
+	}
+}
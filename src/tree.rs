@@ -1,4 +1,12 @@
 //use std::iter; 
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::slice;
+use std::vec;
 //struct Node {
 //    children: Vec<Node>,
 //    data: usize,
@@ -41,62 +49,360 @@
 
 
 
-trait Hierarchical<T> {
+/// Tree whose nodes carry data of type `T` and own their children.
+///
+/// Methods taking an index panic when it is out of range, like the methods of
+/// `Vec`, unless they return an `Option`.
+pub trait Hierarchical<T> {
 	fn new(data: T) -> Self;
 	fn get_data(&self) -> &T;
+	fn get_data_mut(&mut self) -> &mut T;
+	/// Replaces the data of the node and returns the previous data.
+	fn set_data(&mut self, data: T) -> T;
 	fn add_child(&mut self, data: T) -> &mut Self;
+	/// Appends a subtree, for example one removed from another node.
+	fn add_node(&mut self, node: Self) -> &mut Self where Self: Sized;
+	fn insert_child(&mut self, index: usize, data: T) -> &mut Self;
+	fn insert_node(&mut self, index: usize, node: Self) -> &mut Self where Self: Sized;
+	/// Detaches the child with its subtree, `None` when there is no such child.
+	fn remove_child(&mut self, index: usize) -> Option<Self> where Self: Sized;
+	/// Puts the subtree in place of the child and returns the child.
+	fn replace_child(&mut self, index: usize, node: Self) -> Self where Self: Sized;
+	fn swap_children(&mut self, first: usize, second: usize);
+	/// Moves the child at `from` so it ends up at `to`, shifting the children in between.
+	fn move_child(&mut self, from: usize, to: usize);
+	fn sort_children_by<F: FnMut(&Self, &Self) -> Ordering>(&mut self, compare: F) where Self: Sized;
 	fn get_child(&mut self, index: usize) -> &mut Self;
 	fn get_child_save(&mut self, index: usize) -> Option<&mut Self>;
-	fn get_child_data(&mut self, index: usize) -> &T;
+	fn get_child_ref(&self, index: usize) -> Option<&Self>;
+	fn get_child_data(&self, index: usize) -> &T;
+	fn get_children(&self) -> &[Self] where Self: Sized;
+	fn get_children_mut(&mut self) -> &mut [Self] where Self: Sized;
 	fn get_count(&self) -> usize;
+	fn into_data(self) -> T;
+	fn into_children(self) -> Vec<Self> where Self: Sized;
 }
 
 
-#[derive(Debug, PartialEq)]
-struct Node<T> {
-	children: Vec<Node<T>>,
+/// Clones, comparisons and drops walk the tree with a stack on the heap, so
+/// deep trees do not overflow the call stack.
+#[derive(Debug)]
+pub struct Node<T> {
+	children: Children<T>,
 	data: T,
 }
 
 
+// the children own the drop, which leaves `Node` free to be taken apart
+struct Children<T>(Vec<Node<T>>);
 
-#[derive(Debug, PartialEq)]
-struct Item {
-	index: usize,
+
+impl<T> Deref for Children<T> {
+	type Target = Vec<Node<T>>;
+	fn deref(&self) -> &Vec<Node<T>> {
+		&self.0
+	}
 }
 
 
+impl<T> DerefMut for Children<T> {
+	fn deref_mut(&mut self) -> &mut Vec<Node<T>> {
+		&mut self.0
+	}
+}
 
-impl<T> IntoIterator for Node<T> {
-	type Item = Node<T>;
-	type IntoIter = NodeIntoIterator<T>;
 
-	fn into_iter(self) -> Self::IntoIter {
-		NodeIntoIterator::new(&self)
+impl<T: fmt::Debug> fmt::Debug for Children<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+
+impl<T> Drop for Children<T> {
+	fn drop(&mut self) {
+		// every node is dropped after its children were moved onto the stack
+		let mut stack = mem::take(&mut self.0);
+		while let Some(mut node) = stack.pop() {
+			stack.append(&mut node.children.0);
+		}
 	}
+}
+
 
+impl<T: Clone> Clone for Node<T> {
+	fn clone(&self) -> Self {
+		// copies whose children are being cloned, each with the children left
+		let mut stack = vec![(Node::new(self.data.clone()), self.children.iter())];
+		loop {
+			let next = stack.last_mut().and_then(|&mut (_, ref mut children)| children.next());
+			match next {
+				Some(child) => stack.push((Node::new(child.data.clone()), child.children.iter())),
+				None => {
+					let (node, _) = stack.pop().unwrap();
+					match stack.last_mut() {
+						Some(&mut (ref mut parent, _)) => parent.children.push(node),
+						None => return node,
+					}
+				},
+			}
+		}
+	}
 }
 
 
-impl<T> Iterator for NodeIntoIterator<T> {
-	type Item = Node<T>;
-	fn next(&mut self) -> Option<Self::Item> {
-		None
+impl<T: PartialEq> PartialEq for Node<T> {
+	fn eq(&self, other: &Self) -> bool {
+		let mut stack = vec![(self, other)];
+		while let Some((node, other)) = stack.pop() {
+			if node.data != other.data || node.children.len() != other.children.len() {
+				return false;
+			}
+			stack.extend(node.children.iter().zip(other.children.iter()));
+		}
+		true
 	}
 }
 
 
-struct NodeIntoIterator<T> {
-	nodelist: Vec<Node<T>>,
+impl<T: Eq> Eq for Node<T> {}
+
+
+
+/// Order in which a traversal visits the nodes of a tree.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TraversalOrder {
+	/// Depth first, every node before its children.
+	PreOrder,
+	/// Depth first, every node after its children.
+	PostOrder,
+	/// Breadth first, level by level.
+	LevelOrder,
 }
 
 
-impl<T> NodeIntoIterator<T> {
-	fn new(node: &Node<T>) -> NodeIntoIterator<T> {
-		NodeIntoIterator {
-			nodelist: vec![],
+/// Node or node reference that a traversal splits into the item it yields and
+/// the children it visits next.
+pub trait Traversable: Sized {
+	type Item;
+	type Children: Iterator<Item = Self>;
+	fn split(self) -> (Self::Item, Self::Children);
+}
+
+
+impl<'a, T> Traversable for &'a Node<T> {
+	type Item = &'a Node<T>;
+	type Children = slice::Iter<'a, Node<T>>;
+	fn split(self) -> (Self::Item, Self::Children) {
+		(self, self.children.iter())
+	}
+}
+
+
+impl<'a, T> Traversable for &'a mut Node<T> {
+	type Item = &'a mut T;
+	type Children = slice::IterMut<'a, Node<T>>;
+	fn split(self) -> (Self::Item, Self::Children) {
+		(&mut self.data, self.children.iter_mut())
+	}
+}
+
+
+impl<T> Traversable for Node<T> {
+	type Item = T;
+	type Children = vec::IntoIter<Node<T>>;
+	fn split(mut self) -> (Self::Item, Self::Children) {
+		let children = mem::take(&mut self.children.0);
+		(self.data, children.into_iter())
+	}
+}
+
+
+/// Iterator over a tree in one of the traversal orders. Borrowed traversals
+/// yield the nodes, mutable ones the data of the nodes and owning ones the
+/// data taken out of the tree.
+pub struct Traversal<N: Traversable> {
+	order: TraversalOrder,
+	// open nodes with their item while it is held back for post-order, and their depth
+	stack: Vec<(Option<N::Item>, N::Children, usize)>,
+	queue: VecDeque<(N, usize)>,
+	pending: Option<N>,
+}
+
+
+pub type Iter<'a, T> = Traversal<&'a Node<T>>;
+pub type IterMut<'a, T> = Traversal<&'a mut Node<T>>;
+pub type NodeIntoIterator<T> = Traversal<Node<T>>;
+
+
+impl<N: Traversable> Traversal<N> {
+	/// Traversal of the node and all its descendants.
+	pub fn new(node: N, order: TraversalOrder) -> Traversal<N> {
+		let mut traversal = Traversal::empty(order);
+		match order {
+			TraversalOrder::LevelOrder => traversal.queue.push_back((node, 0)),
+			_ => traversal.pending = Some(node),
+		}
+		traversal
+	}
+
+	/// Traversal of the descendants of the node, without the node itself.
+	pub fn descendants(node: N, order: TraversalOrder) -> Traversal<N> {
+		let mut traversal = Traversal::empty(order);
+		let (_, children) = node.split();
+		match order {
+			TraversalOrder::LevelOrder => traversal.queue.extend(children.map(|child| (child, 1))),
+			_ => traversal.stack.push((None, children, 0)),
+		}
+		traversal
+	}
+
+	fn empty(order: TraversalOrder) -> Traversal<N> {
+		Traversal {
+			order,
+			stack: vec![],
+			queue: VecDeque::new(),
+			pending: None,
 		}
 	}
+
+	/// Yields every item with its depth below the node the traversal started from.
+	pub fn with_depth(self) -> TraversalWithDepth<N> {
+		TraversalWithDepth {
+			traversal: self,
+		}
+	}
+
+	fn next_with_depth(&mut self) -> Option<(usize, N::Item)> {
+		match self.order {
+			TraversalOrder::PreOrder => {
+				let (node, depth) = match self.pending.take() {
+					Some(node) => (node, 0),
+					None => loop {
+						let child = match self.stack.last_mut() {
+							Some(&mut (_, ref mut children, depth)) => children.next().map(|child| (child, depth + 1)),
+							None => return None,
+						};
+						match child {
+							Some(found) => break found,
+							None => {
+								self.stack.pop();
+							},
+						}
+					},
+				};
+				let (item, children) = node.split();
+				self.stack.push((None, children, depth));
+				Some((depth, item))
+			},
+			TraversalOrder::PostOrder => {
+				if let Some(node) = self.pending.take() {
+					let (item, children) = node.split();
+					self.stack.push((Some(item), children, 0));
+				}
+				loop {
+					let child = match self.stack.last_mut() {
+						Some(&mut (_, ref mut children, depth)) => children.next().map(|child| (child, depth + 1)),
+						None => return None,
+					};
+					match child {
+						Some((child, depth)) => {
+							let (item, children) = child.split();
+							self.stack.push((Some(item), children, depth));
+						},
+						None => if let Some((Some(item), _, depth)) = self.stack.pop() {
+							return Some((depth, item));
+						},
+					}
+				}
+			},
+			TraversalOrder::LevelOrder => {
+				let (node, depth) = self.queue.pop_front()?;
+				let (item, children) = node.split();
+				self.queue.extend(children.map(|child| (child, depth + 1)));
+				Some((depth, item))
+			},
+		}
+	}
+}
+
+
+impl<N: Traversable> Iterator for Traversal<N> {
+	type Item = N::Item;
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_with_depth().map(|(_, item)| item)
+	}
+}
+
+
+pub struct TraversalWithDepth<N: Traversable> {
+	traversal: Traversal<N>,
+}
+
+
+impl<N: Traversable> Iterator for TraversalWithDepth<N> {
+	type Item = (usize, N::Item);
+	fn next(&mut self) -> Option<Self::Item> {
+		self.traversal.next_with_depth()
+	}
+}
+
+
+impl<T> Node<T> {
+	/// Nodes of the tree in pre-order, starting with this one.
+	pub fn iter(&self) -> Iter<'_, T> {
+		Traversal::new(self, TraversalOrder::PreOrder)
+	}
+	/// Data of the nodes in pre-order, starting with this one.
+	pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+		Traversal::new(self, TraversalOrder::PreOrder)
+	}
+	pub fn traverse(&self, order: TraversalOrder) -> Iter<'_, T> {
+		Traversal::new(self, order)
+	}
+	pub fn traverse_mut(&mut self, order: TraversalOrder) -> IterMut<'_, T> {
+		Traversal::new(self, order)
+	}
+	/// Takes the data out of the tree in the given order.
+	pub fn into_traversal(self, order: TraversalOrder) -> NodeIntoIterator<T> {
+		Traversal::new(self, order)
+	}
+	pub fn descendants(&self, order: TraversalOrder) -> Iter<'_, T> {
+		Traversal::descendants(self, order)
+	}
+	pub fn descendants_mut(&mut self, order: TraversalOrder) -> IterMut<'_, T> {
+		Traversal::descendants(self, order)
+	}
+}
+
+
+impl<T> IntoIterator for Node<T> {
+	type Item = T;
+	type IntoIter = NodeIntoIterator<T>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.into_traversal(TraversalOrder::PreOrder)
+	}
+}
+
+
+impl<'a, T> IntoIterator for &'a Node<T> {
+	type Item = &'a Node<T>;
+	type IntoIter = Iter<'a, T>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+
+impl<'a, T> IntoIterator for &'a mut Node<T> {
+	type Item = &'a mut T;
+	type IntoIter = IterMut<'a, T>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter_mut()
+	}
 }
 
 
@@ -104,14 +410,17 @@ impl<T> NodeIntoIterator<T> {
 impl<T> Hierarchical<T> for Node<T> {
 	fn new(data: T) -> Self {
 		Node {
-			children: vec!(),
-			data: data,
+			children: Children(vec!()),
+			data,
 		}
 	}
 
 	fn add_child(&mut self, data: T) -> &mut Self {
-		let child = Self::new(data);
-		self.children.push(child);
+		self.add_node(Self::new(data))
+	}
+
+	fn add_node(&mut self, node: Self) -> &mut Self {
+		self.children.push(node);
 		let count: usize;
 		{
 			count = self.get_count()-1; 
@@ -119,30 +428,88 @@ impl<T> Hierarchical<T> for Node<T> {
 		&mut self.children[count]
 	}
 
+	fn insert_child(&mut self, index: usize, data: T) -> &mut Self {
+		self.insert_node(index, Self::new(data))
+	}
+
+	fn insert_node(&mut self, index: usize, node: Self) -> &mut Self {
+		self.children.insert(index, node);
+		&mut self.children[index]
+	}
+
+	fn remove_child(&mut self, index: usize) -> Option<Self> {
+		if index < self.children.len() {
+			Some(self.children.remove(index))
+		}
+		else {
+			None
+		}
+	}
+
+	fn replace_child(&mut self, index: usize, node: Self) -> Self {
+		mem::replace(&mut self.children[index], node)
+	}
+
+	fn swap_children(&mut self, first: usize, second: usize) {
+		self.children.swap(first, second);
+	}
+
+	fn move_child(&mut self, from: usize, to: usize) {
+		let child = self.children.remove(from);
+		self.children.insert(to, child);
+	}
+
+	fn sort_children_by<F: FnMut(&Self, &Self) -> Ordering>(&mut self, compare: F) {
+		self.children.sort_by(compare);
+	}
+
 	fn get_data(&self) -> &T {
 		&self.data
 	}
 
+	fn get_data_mut(&mut self) -> &mut T {
+		&mut self.data
+	}
+
+	fn set_data(&mut self, data: T) -> T {
+		mem::replace(&mut self.data, data)
+	}
+
 	fn get_child(&mut self, index: usize) -> &mut Self {
 		&mut self.children[index]
 	}
 
-	fn get_child_data(&mut self, index: usize) -> &T {
-		&self.children[index].get_data()
+	fn get_child_data(&self, index: usize) -> &T {
+		self.children[index].get_data()
 	}
 
 	fn get_child_save(&mut self, index: usize) -> Option<&mut Self> {
-		if index < self.children.len() {
-			Some(&mut self.children[index])
-		}
-		else {
-			None
-		}
+		self.children.get_mut(index)
+	}
+
+	fn get_child_ref(&self, index: usize) -> Option<&Self> {
+		self.children.get(index)
 	}
-	    
+
+	fn get_children(&self) -> &[Self] {
+		&self.children
+	}
+
+	fn get_children_mut(&mut self) -> &mut [Self] {
+		&mut self.children
+	}
+
 	fn get_count(&self) -> usize {
 		self.children.len()
 	}
+
+	fn into_data(self) -> T {
+		self.data
+	}
+
+	fn into_children(mut self) -> Vec<Self> {
+		mem::take(&mut self.children.0)
+	}
 }
 
 
@@ -151,7 +518,12 @@ impl<T> Hierarchical<T> for Node<T> {
 mod tests {
 	use super::Hierarchical;
 	use super::Node;
-	use super::Item;
+	use super::TraversalOrder;
+
+	#[derive(Debug, Clone, PartialEq)]
+	struct Item {
+		index: usize,
+	}
 
 	#[test]
 	fn new_node_test() {
@@ -187,4 +559,130 @@ mod tests {
 		assert_eq!(hierarchy.get_child(0).get_child(0).get_data(), &Item{ index: 2 });
 		assert_eq!(hierarchy.get_child(0).get_child_data(0), &Item{ index: 2 });
 	}
+
+	fn children(node: &Node<Item>) -> Vec<usize> {
+		node.get_children().iter().map(|child| child.get_data().index).collect()
+	}
+
+	#[test]
+	fn insert_remove_replace_test() {
+		let mut hierarchy = Node::new(Item{ index: 0 });
+		hierarchy.add_child(Item{ index: 1 });
+		hierarchy.add_child(Item{ index: 3 });
+		assert_eq!(hierarchy.insert_child(1, Item{ index: 2 }).get_data(), &Item{ index: 2 });
+		assert_eq!(children(&hierarchy), vec![1, 2, 3]);
+		hierarchy.get_child(1).add_child(Item{ index: 4 });
+		let removed = hierarchy.remove_child(1).unwrap();
+		assert_eq!(removed.get_child_data(0), &Item{ index: 4 });
+		assert_eq!(children(&hierarchy), vec![1, 3]);
+		assert!(hierarchy.remove_child(2).is_none());
+		let replaced = hierarchy.replace_child(0, removed);
+		assert_eq!(replaced.into_data(), Item{ index: 1 });
+		assert_eq!(children(&hierarchy), vec![2, 3]);
+		assert_eq!(hierarchy.get_child_ref(0).unwrap().get_child_data(0), &Item{ index: 4 });
+		assert!(hierarchy.get_child_ref(2).is_none());
+		assert_eq!(hierarchy.set_data(Item{ index: 5 }), Item{ index: 0 });
+		assert_eq!(hierarchy.get_data(), &Item{ index: 5 });
+	}
+
+	#[test]
+	fn reattach_subtree_test() {
+		let mut hierarchy = Node::new(Item{ index: 0 });
+		hierarchy.add_child(Item{ index: 1 }).add_child(Item{ index: 2 });
+		hierarchy.add_child(Item{ index: 3 });
+		let subtree = hierarchy.get_child(0).remove_child(0).unwrap();
+		hierarchy.get_child(1).add_node(subtree);
+		assert_eq!(hierarchy.get_child(0).get_count(), 0);
+		assert_eq!(hierarchy.get_child(1).get_child_data(0), &Item{ index: 2 });
+		let subtree = hierarchy.remove_child(1).unwrap();
+		hierarchy.insert_node(0, subtree);
+		assert_eq!(children(&hierarchy), vec![3, 1]);
+		assert_eq!(children(&hierarchy.into_children().remove(0)), vec![2]);
+	}
+
+	#[test]
+	fn reorder_children_test() {
+		let mut hierarchy = Node::new(Item{ index: 0 });
+		for index in 1..5 {
+			hierarchy.add_child(Item{ index });
+		}
+		hierarchy.swap_children(0, 3);
+		assert_eq!(children(&hierarchy), vec![4, 2, 3, 1]);
+		hierarchy.move_child(0, 2);
+		assert_eq!(children(&hierarchy), vec![2, 3, 4, 1]);
+		hierarchy.move_child(3, 0);
+		assert_eq!(children(&hierarchy), vec![1, 2, 3, 4]);
+		hierarchy.sort_children_by(|a, b| b.get_data().index.cmp(&a.get_data().index));
+		assert_eq!(children(&hierarchy), vec![4, 3, 2, 1]);
+		hierarchy.get_children_mut()[0].get_data_mut().index = 0;
+		assert_eq!(hierarchy.get_child_data(0), &Item{ index: 0 });
+	}
+
+	#[test]
+	fn deep_tree_test() {
+		let mut hierarchy = Node::new(Item{ index: 0 });
+		for index in 1..100_000 {
+			let mut parent = Node::new(Item{ index });
+			parent.add_node(hierarchy);
+			hierarchy = parent;
+		}
+		let copy = hierarchy.clone();
+		assert_eq!(copy, hierarchy);
+		assert_eq!(copy.iter().map(|node| node.get_data().index).last(), Some(0));
+		hierarchy.get_child(0).get_child(0).get_data_mut().index = 1;
+		assert_ne!(copy, hierarchy);
+	}
+
+	//        0
+	//      /   \
+	//     1     4
+	//    / \     \
+	//   2   3     5
+	fn sample() -> Node<Item> {
+		let mut hierarchy = Node::new(Item{ index: 0 });
+		{
+			let first = hierarchy.add_child(Item{ index: 1 });
+			first.add_child(Item{ index: 2 });
+			first.add_child(Item{ index: 3 });
+		}
+		hierarchy.add_child(Item{ index: 4 }).add_child(Item{ index: 5 });
+		hierarchy
+	}
+
+	#[test]
+	fn traversal_order_test() {
+		let hierarchy = sample();
+		let indices = |order| hierarchy.traverse(order).map(|node| node.get_data().index).collect::<Vec<_>>();
+		assert_eq!(indices(TraversalOrder::PreOrder), vec![0, 1, 2, 3, 4, 5]);
+		assert_eq!(indices(TraversalOrder::PostOrder), vec![2, 3, 1, 5, 4, 0]);
+		assert_eq!(indices(TraversalOrder::LevelOrder), vec![0, 1, 4, 2, 3, 5]);
+		assert_eq!((&hierarchy).into_iter().count(), 6);
+		assert_eq!(Node::new(Item{ index: 0 }).traverse(TraversalOrder::PostOrder).count(), 1);
+	}
+
+	#[test]
+	fn traversal_with_depth_test() {
+		let hierarchy = sample();
+		let depths = |order| hierarchy.descendants(order).with_depth().map(|(depth, node)| (depth, node.get_data().index)).collect::<Vec<_>>();
+		assert_eq!(depths(TraversalOrder::PreOrder), vec![(1, 1), (2, 2), (2, 3), (1, 4), (2, 5)]);
+		assert_eq!(depths(TraversalOrder::PostOrder), vec![(2, 2), (2, 3), (1, 1), (2, 5), (1, 4)]);
+		assert_eq!(depths(TraversalOrder::LevelOrder), vec![(1, 1), (1, 4), (2, 2), (2, 3), (2, 5)]);
+		assert_eq!(hierarchy.iter().with_depth().next().map(|(depth, _)| depth), Some(0));
+		assert_eq!(Node::new(Item{ index: 0 }).descendants(TraversalOrder::PostOrder).count(), 0);
+	}
+
+	#[test]
+	fn mutable_and_owning_traversal_test() {
+		let mut hierarchy = sample();
+		for item in hierarchy.iter_mut() {
+			item.index *= 10;
+		}
+		for item in hierarchy.descendants_mut(TraversalOrder::LevelOrder) {
+			item.index += 1;
+		}
+		let indices = hierarchy.clone().into_iter().map(|item| item.index).collect::<Vec<_>>();
+		assert_eq!(indices, vec![0, 11, 21, 31, 41, 51]);
+		let indices = hierarchy.into_traversal(TraversalOrder::PostOrder).map(|item| item.index).collect::<Vec<_>>();
+		assert_eq!(indices, vec![21, 31, 11, 51, 41, 0]);
+	}
 }
@@ -0,0 +1,312 @@
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use doctype::XmlAttributeDefault;
+use doctype::XmlAttributeDefinition;
+use doctype::XmlAttributeType;
+use doctype::XmlContentParticle;
+use doctype::XmlContentSpec;
+use doctype::XmlDoctype;
+use doctype::XmlEntityValue;
+use doctype::XmlParticleKind;
+use doctype::XmlRepetition;
+use errors::ExtractError;
+use errors::ExtractResult;
+use parser::XmlElement;
+use parser::XmlNode;
+use span::XmlPosition;
+use tokenizer::XmlValidation;
+use tree::Hierarchical;
+
+
+/// Checks a document against the DTD of its DOCTYPE. `positions` holds the
+/// start of every element in document order and is used for errors.
+pub fn validate(document: &XmlNode, positions: &[XmlPosition]) -> ExtractResult<()> {
+	let position = |index: usize| positions.get(index).cloned().unwrap_or_else(XmlPosition::start);
+	let doctype = document.get_data().doctype().ok_or_else(|| ExtractError::MissingDoctype(position(0)))?;
+	if let Some(root) = document.get_children().iter().find_map(|child| child.get_data().name()) {
+		if root != doctype.name {
+			return Err(ExtractError::RootMismatch(doctype.name.clone(), root.to_string(), position(0)));
+		}
+	}
+	let mut validator = XmlValidator { doctype, ids: HashSet::new(), references: vec![], index: 0 };
+	for child in document.get_children() {
+		validator.validate_node(child, &position)?;
+	}
+	// references may point forward, so they are resolved at the end
+	for (reference, index) in validator.references {
+		if !validator.ids.contains(&reference) {
+			return Err(ExtractError::UnknownIdRef(reference, position(index)));
+		}
+	}
+	Ok(())
+}
+
+
+struct XmlValidator<'d> {
+	doctype: &'d XmlDoctype,
+	ids: HashSet<String>,
+	references: Vec<(String, usize)>, // IDREF values with the index of their element
+	index: usize, // index of the next element in document order
+}
+
+
+impl<'d> XmlValidator<'d> {
+	fn validate_node<F: Fn(usize) -> XmlPosition>(&mut self, node: &XmlNode, position: &F) -> ExtractResult<()> {
+		// nodes in document order, the children are pushed in reverse
+		let mut stack = vec![node];
+		while let Some(node) = stack.pop() {
+			let name = match node.get_data().name() {
+				Some(name) => name,
+				None => continue,
+			};
+			let index = self.index;
+			self.index += 1;
+			let declaration = self.doctype.element(name).ok_or_else(|| ExtractError::UndeclaredElement(name.to_string(), position(index)))?;
+			if !content_matches(&declaration.content, node.get_children()) {
+				return Err(ExtractError::InvalidContent(name.to_string(), position(index)));
+			}
+			self.validate_attributes(node.get_data(), index, position(index))?;
+			stack.extend(node.get_children().iter().rev());
+		}
+		Ok(())
+	}
+
+	fn validate_attributes(&mut self, element: &XmlElement, index: usize, position: XmlPosition) -> ExtractResult<()> {
+		let name = element.name().unwrap_or_default();
+		let definitions = self.definitions(name);
+		for attribute in element.attributes() {
+			let definition = definitions.iter()
+				.find(|definition| definition.name == attribute.name)
+				.ok_or_else(|| ExtractError::UndeclaredAttribute(name.to_string(), attribute.name.clone(), position))?;
+			self.validate_value(definition, &attribute.value, index, position)?;
+		}
+		for definition in definitions {
+			if definition.default == XmlAttributeDefault::Required && element.attribute(&definition.name).is_none() {
+				return Err(ExtractError::MissingAttribute(name.to_string(), definition.name.clone(), position));
+			}
+		}
+		Ok(())
+	}
+
+	/// Attribute definitions of the element, the first definition of an attribute is binding.
+	fn definitions(&self, element: &str) -> Vec<&'d XmlAttributeDefinition> {
+		let mut definitions: Vec<&XmlAttributeDefinition> = vec![];
+		for list in self.doctype.attribute_lists().filter(|list| list.element == element) {
+			for definition in &list.attributes {
+				if !definitions.iter().any(|known| known.name == definition.name) {
+					definitions.push(definition);
+				}
+			}
+		}
+		definitions
+	}
+
+	fn validate_value(&mut self, definition: &XmlAttributeDefinition, value: &str, index: usize, position: XmlPosition) -> ExtractResult<()> {
+		let invalid = || ExtractError::InvalidAttributeValue(definition.name.clone(), value.to_string(), position);
+		// everything but CDATA is compared with whitespace collapsed
+		let normalized = match definition.kind {
+			XmlAttributeType::CData => value.to_string(),
+			_ => value.split(|c: char| c.is_whitespace_in_xml()).filter(|part| !part.is_empty()).collect::<Vec<_>>().join(" "),
+		};
+		let tokens: Vec<&str> = normalized.split(' ').collect();
+		let valid = match definition.kind {
+			XmlAttributeType::CData => true,
+			XmlAttributeType::Id => {
+				if is_name(&normalized) && !self.ids.insert(normalized.clone()) {
+					return Err(ExtractError::DuplicateId(normalized, position));
+				}
+				is_name(&normalized)
+			},
+			XmlAttributeType::IdRef | XmlAttributeType::IdRefs => {
+				let single = definition.kind == XmlAttributeType::IdRef;
+				let valid = tokens.iter().all(|token| is_name(token)) && (!single || tokens.len() == 1);
+				if valid {
+					self.references.extend(tokens.iter().map(|token| (token.to_string(), index)));
+				}
+				valid
+			},
+			XmlAttributeType::Entity | XmlAttributeType::Entities => {
+				let single = definition.kind == XmlAttributeType::Entity;
+				(!single || tokens.len() == 1) && tokens.iter().all(|token| self.is_unparsed_entity(token))
+			},
+			XmlAttributeType::NmToken => tokens.len() == 1 && is_name_token(&normalized),
+			XmlAttributeType::NmTokens => tokens.iter().all(|token| is_name_token(token)),
+			XmlAttributeType::Notation(ref names) | XmlAttributeType::Enumeration(ref names) => names.contains(&normalized),
+		};
+		if !valid {
+			return Err(invalid());
+		}
+		match definition.default {
+			XmlAttributeDefault::Fixed(ref fixed) if *fixed != normalized && *fixed != value => Err(invalid()),
+			_ => Ok(()),
+		}
+	}
+
+	fn is_unparsed_entity(&self, name: &str) -> bool {
+		self.doctype.entity(name).is_some_and(|entity| matches!(entity.value, XmlEntityValue::External(_, Some(_))))
+	}
+}
+
+
+fn is_name(value: &str) -> bool {
+	value.starts_with(|c: char| c.is_valid_first_char_in_element_name()) && is_name_token(value)
+}
+
+
+fn is_name_token(value: &str) -> bool {
+	!value.is_empty() && value.chars().all(|c| c.is_valid_char_in_element_name())
+}
+
+
+/// Character data is not allowed in element content, whitespace, comments and
+/// processing instructions are.
+fn is_character_data(element: &XmlElement) -> bool {
+	matches!(element, XmlElement::Text(_) | XmlElement::CData(_))
+}
+
+
+fn content_matches(content: &XmlContentSpec, children: &[XmlNode]) -> bool {
+	let names: Vec<&str> = children.iter().filter_map(|child| child.get_data().name()).collect();
+	match content {
+		XmlContentSpec::Empty => children.is_empty(),
+		XmlContentSpec::Any => true,
+		XmlContentSpec::Mixed(allowed) => names.iter().all(|name| allowed.iter().any(|allowed| allowed == name)),
+		XmlContentSpec::Children(particle) => {
+			!children.iter().any(|child| is_character_data(child.get_data())) &&
+				match_particle(particle, &names, 0).contains(&names.len())
+		},
+	}
+}
+
+
+/// All positions in `names` where a match of the particle starting at `start` can end.
+fn match_particle(particle: &XmlContentParticle, names: &[&str], start: usize) -> BTreeSet<usize> {
+	let once = |start: usize| match particle.kind {
+		XmlParticleKind::Name(ref name) => names.get(start).filter(|found| **found == name).map(|_| start + 1).into_iter().collect(),
+		XmlParticleKind::Sequence(ref particles) => particles.iter().fold(Some(start).into_iter().collect(), |ends: BTreeSet<usize>, particle| {
+			ends.iter().flat_map(|&end| match_particle(particle, names, end)).collect()
+		}),
+		XmlParticleKind::Choice(ref particles) => particles.iter().flat_map(|particle| match_particle(particle, names, start)).collect(),
+	};
+	let mut ends: BTreeSet<usize> = once(start);
+	match particle.repetition {
+		XmlRepetition::One => (),
+		XmlRepetition::Optional => {
+			ends.insert(start);
+		},
+		XmlRepetition::ZeroOrMore | XmlRepetition::OneOrMore => {
+			if particle.repetition == XmlRepetition::ZeroOrMore {
+				ends.insert(start);
+			}
+			// repeat from every new end until no further end is reached
+			let mut pending: Vec<usize> = ends.iter().cloned().filter(|&end| end > start).collect();
+			while let Some(end) = pending.pop() {
+				for next in once(end) {
+					if ends.insert(next) {
+						pending.push(next);
+					}
+				}
+			}
+		},
+	}
+	ends
+}
+
+
+#[cfg(test)]
+mod tests {
+	use errors::ExtractError;
+	use options::XmlLimits;
+	use options::XmlLineEndings;
+	use options::XmlOptions;
+	use parser::XmlElement;
+	use parser::XmlParser;
+	use span::XmlPosition;
+	use token::XmlWhitespace;
+	use tokenizer::XmlTokenize;
+	use tokenizer_iterator::XmlTokenizer;
+	use tree::Hierarchical;
+
+	const DTD: &str = "<!DOCTYPE book [
+		<!ELEMENT book (title, (chapter | appendix)+, index?)>
+		<!ELEMENT title (#PCDATA)>
+		<!ELEMENT chapter (#PCDATA | em | ref)*>
+		<!ELEMENT appendix EMPTY>
+		<!ELEMENT index ANY>
+		<!ELEMENT em (#PCDATA)>
+		<!ELEMENT ref EMPTY>
+		<!ATTLIST book lang (en | de) 'en' version CDATA #FIXED '1.0'>
+		<!ATTLIST chapter id ID #REQUIRED pages NMTOKENS #IMPLIED>
+		<!ATTLIST ref to IDREF #REQUIRED>
+	]>\n";
+
+	fn validate(body: &str) -> Result<(), ExtractError> {
+		format!("{}{}", DTD, body).tokenize()?.parse_with(&XmlOptions::new().with_validate(true)).map(|_| ())
+	}
+
+	#[test]
+	fn valid_document() {
+		validate("<book><title>T</title><chapter id='c1'>a <em>b</em> <ref to='c2'/></chapter>\n<chapter id='c2' pages=' 1  2 '/><index><em/></index></book>").unwrap();
+		validate("<book lang='de' version='1.0'>\n\t<title/>\n\t<appendix/>\n</book>").unwrap();
+	}
+	#[test]
+	fn invalid_content() {
+		let content = |body: &str| match validate(body) {
+			Err(ExtractError::InvalidContent(name, _)) => name,
+			other => panic!("unexpected result {:?}", other),
+		};
+		assert_eq!(content("<book><title/></book>"), "book");
+		assert_eq!(content("<book><appendix/><title/></book>"), "book");
+		assert_eq!(content("<book><title/><appendix/><index/><index/></book>"), "book");
+		assert_eq!(content("<book><title/>text<appendix/></book>"), "book");
+		assert_eq!(content("<book><title/><appendix> </appendix></book>"), "appendix");
+		assert_eq!(content("<book><title><em/></title><appendix/></book>"), "title");
+	}
+	#[test]
+	fn invalid_elements() {
+		assert!(matches!("<book/>".tokenize().unwrap().parse_with(&XmlOptions::new().with_validate(true)), Err(ExtractError::MissingDoctype(_))));
+		match validate("<title/>") {
+			Err(ExtractError::RootMismatch(declared, found, _)) => assert_eq!((declared.as_str(), found.as_str()), ("book", "title")),
+			other => panic!("unexpected result {:?}", other),
+		}
+		match validate("<book><title/><appendix/><index>\n  <section/></index></book>") {
+			Err(ExtractError::UndeclaredElement(name, position)) => {
+				assert_eq!(name, "section");
+				assert_eq!(position, XmlPosition::new(447, 14, 3));
+			},
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn invalid_attributes() {
+		assert!(matches!(validate("<book lang='fr'><title/><appendix/></book>"), Err(ExtractError::InvalidAttributeValue(_, _, _))));
+		assert!(matches!(validate("<book version='2.0'><title/><appendix/></book>"), Err(ExtractError::InvalidAttributeValue(_, _, _))));
+		assert!(matches!(validate("<book><title/><chapter id='1c'/></book>"), Err(ExtractError::InvalidAttributeValue(_, _, _))));
+		assert!(matches!(validate("<book><title/><chapter/></book>"), Err(ExtractError::MissingAttribute(_, _, _))));
+		match validate("<book><title lang='en'/><appendix/></book>") {
+			Err(ExtractError::UndeclaredAttribute(element, attribute, _)) => assert_eq!((element.as_str(), attribute.as_str()), ("title", "lang")),
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+	#[test]
+	fn validate_with_options() {
+		let depth = 100_000;
+		let text = format!("<!DOCTYPE a [<!ELEMENT a (a?)>]>\r\n{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+		let options = XmlOptions::new().with_validate(true).with_line_endings(XmlLineEndings::Normalize).with_limits(XmlLimits { max_depth: depth, ..XmlLimits::default() });
+		let tree = XmlTokenizer::with_options(text.chars(), &options).parse_with(&options).unwrap();
+		assert_eq!(tree.get_child_data(0), &XmlElement::Whitespace(XmlWhitespace::new("\n".to_string()).unwrap()));
+		let options = options.with_limits(XmlLimits { max_depth: 2, ..XmlLimits::default() });
+		assert!(matches!("<!DOCTYPE a [<!ELEMENT a (a?)>]><a><a><a/></a></a>".tokenize().unwrap().parse_with(&options), Err(ExtractError::NestingTooDeep(2, _))));
+	}
+	#[test]
+	fn invalid_ids() {
+		match validate("<book><title/><chapter id='c1'/><chapter id=' c1'/></book>") {
+			Err(ExtractError::DuplicateId(id, _)) => assert_eq!(id, "c1"),
+			other => panic!("unexpected result {:?}", other),
+		}
+		match validate("<book><title/><chapter id='c1'><ref to='c2'/></chapter></book>") {
+			Err(ExtractError::UnknownIdRef(id, _)) => assert_eq!(id, "c2"),
+			other => panic!("unexpected result {:?}", other),
+		}
+	}
+}
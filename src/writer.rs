@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+use std::io;
+use std::io::Write;
+use attribute::XmlAttribute;
+use declaration::XmlDeclaration;
+use options::XmlAttributeWrapping;
+use options::XmlDeclarationOutput;
+use options::XmlWriteOptions;
+use parser::XmlElement;
+use parser::XmlNode;
+use token::XmlToken;
+use tokenizer::XmlValidation;
+use tree::Hierarchical;
+
+
+// name, depth, children, index of the next child and whether the children are indented
+type XmlOpenElement<'n> = (&'n str, usize, Vec<&'n XmlNode>, usize, bool);
+
+
+/// Writes parsed trees back as XML text.
+pub struct XmlWriter<W> {
+	writer: W,
+	options: XmlWriteOptions,
+}
+
+
+impl<W: Write> XmlWriter<W> {
+	pub fn new(writer: W) -> XmlWriter<W> {
+		XmlWriter::with_options(writer, &XmlWriteOptions::default())
+	}
+	pub fn with_options(writer: W, options: &XmlWriteOptions) -> XmlWriter<W> {
+		XmlWriter {
+			writer,
+			options: options.clone(),
+		}
+	}
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+
+	/// Writes a document or the subtree of any other node. Comments and
+	/// processing instructions that would end early fail with `InvalidData`.
+	pub fn write(&mut self, node: &XmlNode) -> io::Result<()> {
+		match *node.get_data() {
+			XmlElement::Document { ref declaration, ref doctype } => {
+				let declaration = match self.options.declaration {
+					XmlDeclarationOutput::Keep => declaration.clone(),
+					XmlDeclarationOutput::Always => Some(declaration.clone().unwrap_or_else(|| XmlDeclaration::new("1.0".to_string(), None, None))),
+					XmlDeclarationOutput::Omit => None,
+				};
+				if let Some(declaration) = declaration {
+					self.write_token(XmlToken::Declaration(declaration))?;
+					self.write_line_break()?;
+				}
+				if let Some(ref doctype) = *doctype {
+					self.write_token(XmlToken::Doctype(doctype.clone()))?;
+					self.write_line_break()?;
+				}
+				// whitespace outside the root element is insignificant
+				let pretty = self.options.indent.is_some();
+				for child in node.get_children() {
+					if let XmlElement::Whitespace(_) = *child.get_data() {
+						if pretty || !self.options.preserve_whitespace {
+							continue;
+						}
+					}
+					self.write_node(child, 0)?;
+					self.write_line_break()?;
+				}
+				Ok(())
+			},
+			_ => self.write_node(node, 0),
+		}
+	}
+
+	/// Writes the node with its subtree. Open elements are kept on a stack
+	/// and closed when popped, so deep trees do not overflow the call stack.
+	fn write_node(&mut self, node: &XmlNode, depth: usize) -> io::Result<()> {
+		let mut stack = vec![];
+		self.write_open(node, depth, &mut stack)?;
+		loop {
+			let (child, depth, indented) = match stack.last_mut() {
+				Some(&mut (_, depth, ref children, ref mut next, indented)) => {
+					let child = children.get(*next).cloned();
+					*next += 1;
+					(child, depth, indented)
+				},
+				None => return Ok(()),
+			};
+			match child {
+				Some(child) => {
+					if indented {
+						self.write_indent(depth + 1)?;
+					}
+					self.write_open(child, depth + 1, &mut stack)?;
+				},
+				None => {
+					let (name, depth, children, _, indented) = stack.pop().unwrap();
+					if indented && !children.is_empty() {
+						self.write_indent(depth)?;
+					}
+					write!(self.writer, "</{}>", name)?;
+				},
+			}
+		}
+	}
+
+	/// Writes a node without its children, elements with content are pushed
+	/// to be closed after them.
+	fn write_open<'n>(&mut self, node: &'n XmlNode, depth: usize, stack: &mut Vec<XmlOpenElement<'n>>) -> io::Result<()> {
+		match *node.get_data() {
+			XmlElement::Document { .. } => self.write(node),
+			XmlElement::Element { ref name, ref attributes, .. } => {
+				let text = node.get_children().iter().any(|child| is_text(child.get_data()));
+				let children: Vec<&XmlNode> = node.get_children().iter()
+					.filter(|child| self.options.preserve_whitespace || text || !is_whitespace(child.get_data()))
+					.collect();
+				let empty = children.is_empty() && self.options.self_closing;
+				self.write_start_tag(name, attributes, empty, depth)?;
+				if !empty {
+					// indentation would change mixed content
+					let indented = self.options.indent.is_some() && !children.iter().any(|child| is_text(child.get_data()) || is_whitespace(child.get_data()));
+					stack.push((name, depth, children, 0, indented));
+				}
+				Ok(())
+			},
+			XmlElement::Text(ref text) => self.write_token(XmlToken::Text(Cow::Borrowed(text))),
+			XmlElement::Whitespace(ref whitespace) => write!(self.writer, "{}", whitespace),
+			XmlElement::Comment(ref text) => {
+				if text.contains("--") || text.ends_with('-') {
+					return Err(invalid_data(format!("comment {:?} contains \"--\" or ends with \"-\"", text)));
+				}
+				self.write_token(XmlToken::Comment(Cow::Borrowed(text)))
+			},
+			// a section can not contain its end, so it is split after "]]"
+			XmlElement::CData(ref text) => self.write_token(XmlToken::CData(Cow::Owned(text.replace("]]>", "]]]]><![CDATA[>")))),
+			XmlElement::ProcessingInstruction { ref target, ref data } => {
+				if target.is_empty() || target.contains(|c: char| c.is_whitespace_in_xml() || c == '?') || data.contains("?>") {
+					return Err(invalid_data(format!("processing instruction {:?} can not be written", target)));
+				}
+				self.write_token(XmlToken::ProcessingInstruction(Cow::Borrowed(target), Cow::Borrowed(data)))
+			},
+		}
+	}
+
+	fn write_start_tag(&mut self, name: &str, attributes: &[XmlAttribute], empty: bool, depth: usize) -> io::Result<()> {
+		let attributes: Vec<String> = attributes.iter().map(XmlAttribute::to_string).collect();
+		let wrapped = match self.options.attribute_wrapping {
+			XmlAttributeWrapping::Never => false,
+			XmlAttributeWrapping::OnOverflow => {
+				let indent = self.options.indent.as_ref().map_or(0, |indent| indent.chars().count() * depth);
+				let tag = name.chars().count() + if empty { 3 } else { 2 };
+				let length = attributes.iter().fold(indent + tag, |length, attribute| length + attribute.chars().count() + 1);
+				attributes.len() > 1 && length > self.options.line_width
+			},
+			XmlAttributeWrapping::Always => attributes.len() > 1,
+		};
+		write!(self.writer, "<{}", name)?;
+		for attribute in &attributes {
+			if wrapped {
+				self.write_indent(depth + 1)?;
+			}
+			else {
+				write!(self.writer, " ")?;
+			}
+			write!(self.writer, "{}", attribute)?;
+		}
+		write!(self.writer, "{}", if empty { "/>" } else { ">" })
+	}
+
+	fn write_token(&mut self, token: XmlToken) -> io::Result<()> {
+		write!(self.writer, "{}", token)
+	}
+
+	fn write_indent(&mut self, depth: usize) -> io::Result<()> {
+		let indent = self.options.indent.as_ref().map_or(String::new(), |indent| indent.repeat(depth));
+		write!(self.writer, "\n{}", indent)
+	}
+
+	fn write_line_break(&mut self) -> io::Result<()> {
+		if self.options.indent.is_some() {
+			writeln!(self.writer)?;
+		}
+		Ok(())
+	}
+}
+
+
+fn invalid_data(message: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+fn is_text(element: &XmlElement) -> bool {
+	matches!(*element, XmlElement::Text(_) | XmlElement::CData(_))
+}
+
+
+fn is_whitespace(element: &XmlElement) -> bool {
+	matches!(*element, XmlElement::Whitespace(_))
+}
+
+
+/// Writes the tree into a string, fails like `XmlWriter::write` on markup
+/// that can not be written.
+pub fn to_string(node: &XmlNode, options: &XmlWriteOptions) -> io::Result<String> {
+	let mut writer = XmlWriter::with_options(vec![], options);
+	writer.write(node)?;
+	Ok(String::from_utf8(writer.into_inner()).expect("the tree holds valid UTF-8"))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use options::XmlAttributeWrapping;
+	use options::XmlDeclarationOutput;
+	use options::XmlWriteOptions;
+	use parser::XmlParser;
+	use tokenizer::XmlTokenize;
+	use super::*;
+
+	fn write(text: &str, options: &XmlWriteOptions) -> String {
+		to_string(&text.tokenize().unwrap().parse().unwrap(), options).unwrap()
+	}
+
+	#[test]
+	fn write_unchanged() {
+		let text = "<?xml version=\"1.0\"?><!DOCTYPE a><a x='&apos;\"'>\n\t<b>1 &lt; 2</b><!-- c --><![CDATA[<d>]]><?pi data?><e/>\n</a>";
+		assert_eq!(write(text, &XmlWriteOptions::new()), text);
+	}
+	#[test]
+	fn write_pretty() {
+		let text = "<?xml version=\"1.0\"?>\n<a>\n<b><c/></b>   <p>Some <i>mixed</i> text</p><e></e></a>";
+		let expected = "<?xml version=\"1.0\"?>\n<a>\n  <b>\n    <c/>\n  </b>\n  <p>Some <i>mixed</i> text</p>\n  <e/>\n</a>\n";
+		assert_eq!(write(text, &XmlWriteOptions::pretty("  ")), expected);
+		let expected = "<a><b><c></c></b><p>Some <i>mixed</i> text</p><e></e></a>";
+		let options = XmlWriteOptions::new().with_preserve_whitespace(false).with_self_closing(false).with_declaration(XmlDeclarationOutput::Omit);
+		assert_eq!(write(text, &options), expected);
+	}
+	#[test]
+	fn write_declaration() {
+		let options = XmlWriteOptions::new().with_declaration(XmlDeclarationOutput::Always);
+		assert_eq!(write("<a/>", &options), "<?xml version=\"1.0\"?><a/>");
+		assert_eq!(write("<?xml version=\"1.1\"?><a/>", &options), "<?xml version=\"1.1\"?><a/>");
+		assert_eq!(write("<a/>", &XmlWriteOptions::new()), "<a/>");
+	}
+	#[test]
+	fn write_wrapped_attributes() {
+		let text = "<a><b first=\"1\" second=\"2\"/><c only=\"1\"/></a>";
+		let options = XmlWriteOptions::pretty("\t").with_attribute_wrapping(XmlAttributeWrapping::Always);
+		assert_eq!(write(text, &options), "<a>\n\t<b\n\t\tfirst=\"1\"\n\t\tsecond=\"2\"/>\n\t<c only=\"1\"/>\n</a>\n");
+		// `<b first="1" second="2"/>` takes 25 characters after one indentation
+		let options = XmlWriteOptions::pretty(" ").with_attribute_wrapping(XmlAttributeWrapping::OnOverflow);
+		assert_eq!(write(text, &options.clone().with_line_width(26)), "<a>\n <b first=\"1\" second=\"2\"/>\n <c only=\"1\"/>\n</a>\n");
+		assert_eq!(write(text, &options.with_line_width(25)), "<a>\n <b\n  first=\"1\"\n  second=\"2\"/>\n <c only=\"1\"/>\n</a>\n");
+	}
+	#[test]
+	fn write_invalid_markup() {
+		let write_child = |child: XmlElement| {
+			let mut tree = XmlNode::new(XmlElement::new_element("a".to_string(), vec![]));
+			tree.add_child(child);
+			to_string(&tree, &XmlWriteOptions::new())
+		};
+		let invalid = |child: XmlElement| write_child(child).unwrap_err().kind() == io::ErrorKind::InvalidData;
+		assert!(invalid(XmlElement::Comment("a -- b".to_string())));
+		assert!(invalid(XmlElement::Comment("a -".to_string())));
+		assert!(invalid(XmlElement::ProcessingInstruction { target: "pi".to_string(), data: "a ?> b".to_string() }));
+		assert!(invalid(XmlElement::ProcessingInstruction { target: "p i".to_string(), data: String::new() }));
+		assert!(invalid(XmlElement::ProcessingInstruction { target: String::new(), data: "a".to_string() }));
+		assert_eq!(write_child(XmlElement::Comment(" a - b ".to_string())).unwrap(), "<a><!-- a - b --></a>");
+		let written = write_child(XmlElement::CData("x]]>y".to_string())).unwrap();
+		assert_eq!(written, "<a><![CDATA[x]]]]><![CDATA[>y]]></a>");
+		let tree = written.tokenize().unwrap().parse().unwrap();
+		let text: String = tree.get_child_ref(0).unwrap().get_children().iter().map(|child| match *child.get_data() {
+			XmlElement::CData(ref text) => text.clone(),
+			_ => String::new(),
+		}).collect();
+		assert_eq!(text, "x]]>y");
+	}
+	#[test]
+	fn write_deep_tree() {
+		let depth = 100_000;
+		let mut tree = XmlNode::new(XmlElement::new_element("a".to_string(), vec![]));
+		for _ in 1..depth {
+			let mut parent = XmlNode::new(XmlElement::new_element("a".to_string(), vec![]));
+			parent.add_node(tree);
+			tree = parent;
+		}
+		let written = to_string(&tree, &XmlWriteOptions::new()).unwrap();
+		assert_eq!(written, format!("{}<a/>{}", "<a>".repeat(depth - 1), "</a>".repeat(depth - 1)));
+		let written = to_string(&tree, &XmlWriteOptions::pretty("")).unwrap();
+		assert_eq!(written.lines().count(), 2 * depth - 1);
+	}
+	#[test]
+	fn write_into_io() {
+		let tree = "<a>text</a>".tokenize().unwrap().parse().unwrap();
+		let mut writer = XmlWriter::new(vec![]);
+		writer.write(tree.get_child_ref(0).unwrap()).unwrap();
+		assert_eq!(writer.into_inner(), b"<a>text</a>".to_vec());
+	}
+}